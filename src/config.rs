@@ -1,14 +1,25 @@
 use crate::{
-    sink,
+    schedule::{self, ScheduleConfig},
+    sink::{
+        self,
+        buffer::{BufferConfig, Buffered},
+    },
     source::{self, GlobalConfig},
-    topology::{Component, Topology},
+    state::{self, StateStore},
+    topology::{Component, DataSourceComponent, Inputs, Topology},
+    transform::{self, Transform},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
 };
-use std::{collections::HashMap, path::Path};
 
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+const DEFAULT_WORKERS: usize = 4;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("error reading file: {0}")]
@@ -23,8 +34,159 @@ pub enum Error {
     #[error("invalid data source {1}: {0}")]
     Source(#[source] source::Error, String),
 
+    #[error("invalid schedule for data source {1}: {0}")]
+    Schedule(#[source] schedule::Error, String),
+
     #[error("invalid sink {1}: {0}")]
     Sink(#[source] sink::Error, String),
+
+    #[error("invalid inputs for {1}: {0}")]
+    Inputs(#[source] glob::PatternError, String),
+
+    #[error("input {0} of {1} does not match any source or transform")]
+    UnmatchedInput(String, String),
+
+    #[error("invalid transform {1}: {0}")]
+    Transform(#[source] transform::Error, String),
+
+    #[error("transform {0} is part of a cycle")]
+    Cycle(String),
+
+    #[error("duplicate component name {0}")]
+    DuplicateName(String),
+
+    #[error("from_date = \"last\" requires a state_dir")]
+    MissingStateDir,
+
+    #[error("error opening state: {0}")]
+    State(#[source] state::Error),
+}
+
+/// A date from the configuration, relative dates being resolved every time
+/// data is collected
+#[derive(Copy, Clone, Debug)]
+pub enum DateSpec {
+    Today,
+
+    Yesterday,
+
+    Date(NaiveDate),
+}
+
+impl DateSpec {
+    fn parse(s: &str, name: &str) -> Result<Self, Error> {
+        let lower = s.to_lowercase();
+
+        match lower.as_str() {
+            "today" => Ok(DateSpec::Today),
+            "yesterday" => Ok(DateSpec::Yesterday),
+            _ => s
+                .parse::<NaiveDate>()
+                .map(DateSpec::Date)
+                .map_err(|_| Error::DateFormat(name.to_string())),
+        }
+    }
+
+    pub fn resolve(&self) -> NaiveDate {
+        match self {
+            DateSpec::Today => {
+                let now = chrono::Utc::now();
+                now.naive_local().date()
+            }
+            DateSpec::Yesterday => {
+                let now = chrono::Utc::now();
+                let today = now.naive_local().date();
+                today.pred_opt().expect("date out of range")
+            }
+            DateSpec::Date(date) => *date,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum FromDate {
+    /// Resume from the last day delivered for the source
+    Last,
+
+    Date(DateSpec),
+}
+
+impl FromDate {
+    fn parse(s: &str, name: &str) -> Result<Self, Error> {
+        if s.eq_ignore_ascii_case("last") {
+            Ok(FromDate::Last)
+        } else {
+            DateSpec::parse(s, name).map(FromDate::Date)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Dates {
+    pub from_date: FromDate,
+
+    pub to_date: DateSpec,
+
+    /// Where to start collecting a source without checkpoint when `from_date` is `last`,
+    /// defaults to `to_date`
+    pub initial_date: Option<DateSpec>,
+}
+
+impl Dates {
+    /// Resolves the collection window of a source given the last day delivered for it
+    pub fn global(&self, last_date: Option<NaiveDate>) -> GlobalConfig {
+        let to_date = self.to_date.resolve();
+
+        let from_date = match self.from_date {
+            FromDate::Last => last_date
+                .or_else(|| self.initial_date.map(|d| d.resolve()))
+                .unwrap_or(to_date),
+            FromDate::Date(date) => date.resolve(),
+        };
+
+        GlobalConfig { from_date, to_date }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SourceRaw {
+    /// Registered type of the source, defaults to the name of the table
+    #[serde(rename = "type")]
+    kind: Option<String>,
+
+    schedule: Option<ScheduleConfig>,
+
+    #[serde(flatten)]
+    config: toml::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SinkRaw {
+    /// Registered type of the sink, defaults to the name of the table
+    #[serde(rename = "type")]
+    kind: Option<String>,
+
+    /// Sources or transforms feeding the sink, all sources when not set
+    inputs: Option<Vec<String>>,
+
+    /// Buffer undelivered points on disk
+    buffer: Option<BufferConfig>,
+
+    #[serde(flatten)]
+    config: toml::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TransformRaw {
+    /// Registered type of the transform, defaults to the name of the table
+    #[serde(rename = "type")]
+    kind: Option<String>,
+
+    /// Sources or transforms feeding the transform
+    inputs: Vec<String>,
+
+    #[serde(flatten)]
+    config: toml::Value,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -33,99 +195,314 @@ struct ConfigRaw {
 
     to_date: String,
 
-    sources: HashMap<String, toml::Value>,
+    initial_date: Option<String>,
+
+    state_dir: Option<String>,
+
+    report: Option<String>,
 
-    sinks: HashMap<String, toml::Value>,
+    workers: Option<usize>,
+
+    /// Default retry settings of the sources and sinks
+    retry: Option<toml::Value>,
+
+    sources: HashMap<String, SourceRaw>,
+
+    #[serde(default)]
+    transforms: HashMap<String, TransformRaw>,
+
+    sinks: HashMap<String, SinkRaw>,
 }
 
 impl ConfigRaw {
-    fn to_config(self) -> Result<Config, Error> {
+    fn into_config(self) -> Result<Config, Error> {
         Ok(Config {
-            from_date: Self::parse_date(&self.from_date, "from_date")?,
-            to_date: Self::parse_date(&self.to_date, "to_date")?,
+            dates: Dates {
+                from_date: FromDate::parse(&self.from_date, "from_date")?,
+                to_date: DateSpec::parse(&self.to_date, "to_date")?,
+                initial_date: self
+                    .initial_date
+                    .map(|d| DateSpec::parse(&d, "initial_date"))
+                    .transpose()?,
+            },
+            state_dir: self.state_dir,
+            report: self.report,
+            workers: self.workers.unwrap_or(DEFAULT_WORKERS),
+            retry: self.retry,
             sources: self.sources,
+            transforms: self.transforms,
             sinks: self.sinks,
         })
     }
+}
 
-    fn parse_date(s: &str, name: &str) -> Result<NaiveDate, Error> {
-        let lower = s.to_lowercase();
+#[derive(Debug)]
+struct Config {
+    dates: Dates,
 
-        match lower.as_str() {
-            "today" => {
-                let now = chrono::Utc::now();
-                Ok(now.naive_local().date())
-            }
-            "yesterday" => {
-                let now = chrono::Utc::now();
-                let today = now.naive_local().date();
-                Ok(today.pred())
-            }
-            _ => s
-                .parse::<NaiveDate>()
-                .map_err(|_| Error::DateFormat(name.to_string())),
-        }
+    state_dir: Option<String>,
+
+    report: Option<String>,
+
+    workers: usize,
+
+    retry: Option<toml::Value>,
+
+    sources: HashMap<String, SourceRaw>,
+
+    transforms: HashMap<String, TransformRaw>,
+
+    sinks: HashMap<String, SinkRaw>,
+}
+
+fn parse_inputs(inputs: &[String], name: &str, upstream: &[&str]) -> Result<Inputs, Error> {
+    let inputs = Inputs::parse(inputs).map_err(|e| Error::Inputs(e, name.to_string()))?;
+
+    let upstream = upstream.iter().copied().filter(|n| *n != name);
+    if let Some(input) = inputs.unmatched(upstream) {
+        return Err(Error::UnmatchedInput(input.to_string(), name.to_string()));
     }
+
+    Ok(inputs)
 }
 
-#[derive(Debug)]
-struct Config {
-    from_date: NaiveDate,
+/// Whether the transform at `from` takes, directly or not, the transform at `to` as input
+fn feeds(transforms: &[Component<dyn Transform>], from: usize, to: usize) -> bool {
+    let mut visited = vec![false; transforms.len()];
+    let mut stack = vec![from];
 
-    to_date: NaiveDate,
+    while let Some(current) = stack.pop() {
+        if current == to {
+            return true;
+        }
 
-    sources: HashMap<String, toml::Value>,
+        if std::mem::replace(&mut visited[current], true) {
+            continue;
+        }
 
-    sinks: HashMap<String, toml::Value>,
+        stack.extend(
+            (0..transforms.len()).filter(|&i| {
+                i != current && transforms[current].inputs.matches(&transforms[i].name)
+            }),
+        );
+    }
+
+    false
 }
 
-impl Config {
-    fn global(&self) -> GlobalConfig {
-        GlobalConfig {
-            from_date: self.from_date,
+/// Orders transforms so that every transform comes after the transforms it takes as input.
+/// A glob pattern matching a transform that in turn takes this one as input, e.g. two transforms
+/// taking `*`, does not feed it; only inputs naming transforms exactly can form a cycle
+fn sort_transforms(
+    mut transforms: Vec<Component<dyn Transform>>,
+) -> Result<Vec<Component<dyn Transform>>, Error> {
+    transforms.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut ignored = Vec::new();
+    for (i, t) in transforms.iter().enumerate() {
+        for (j, other) in transforms.iter().enumerate() {
+            if i != j
+                && t.inputs.matches(&other.name)
+                && !t.inputs.names(&other.name)
+                && feeds(&transforms, j, i)
+            {
+                ignored.push((i, other.name.clone()));
+            }
+        }
+    }
+
+    for (i, name) in ignored {
+        transforms[i].inputs.ignore(&name);
+    }
+
+    let mut sorted = Vec::with_capacity(transforms.len());
+
+    while !transforms.is_empty() {
+        let ready = transforms.iter().position(|t| {
+            !transforms
+                .iter()
+                .any(|other| other.name != t.name && t.inputs.matches(&other.name))
+        });
 
-            to_date: self.to_date,
+        match ready {
+            Some(index) => sorted.push(transforms.remove(index)),
+            None => return Err(Error::Cycle(transforms[0].name.clone())),
         }
     }
+
+    Ok(sorted)
 }
 
 pub fn read(file: impl AsRef<Path>) -> Result<Topology, Error> {
     let content = std::fs::read_to_string(file).map_err(Error::ReadFile)?;
     let config_raw: ConfigRaw = toml::from_str(&content).map_err(Error::Toml)?;
-    let config = config_raw.to_config()?;
-    let global_config = config.global();
+    let config = config_raw.into_config()?;
+
+    if matches!(config.dates.from_date, FromDate::Last) && config.state_dir.is_none() {
+        return Err(Error::MissingStateDir);
+    }
+
+    let state_dir = config.state_dir.map(PathBuf::from);
+    let state = state_dir
+        .as_ref()
+        .map(StateStore::open)
+        .transpose()
+        .map_err(Error::State)?;
 
-    let data_sources = config
-        .sources
+    let mut sources: Vec<_> = config.sources.into_iter().collect();
+    sources.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let data_sources = sources
         .into_iter()
         .map(|(k, v)| {
-            let component = source::Registration::build(&k, v, global_config)
+            let schedule = v
+                .schedule
+                .map(|s| s.parse())
+                .transpose()
+                .map_err(|e| Error::Schedule(e, k.clone()))?;
+
+            let kind = v.kind.as_deref().unwrap_or(&k);
+            let component = source::Registration::build(kind, v.config, config.retry.as_ref())
                 .map_err(|e| Error::Source(e, k.clone()))?;
 
-            Ok(Component {
+            Ok(DataSourceComponent {
                 name: k.clone(),
+
+                schedule,
+
                 component,
             })
         })
         .collect::<Result<Vec<_>, _>>()?;
 
-    let sinks = config
-        .sinks
+    let source_names: Vec<_> = data_sources.iter().map(|s| s.name.as_str()).collect();
+
+    if let Some(name) = config
+        .transforms
+        .keys()
+        .find(|k| source_names.contains(&k.as_str()))
+    {
+        return Err(Error::DuplicateName(name.clone()));
+    }
+
+    let upstream: Vec<_> = source_names
+        .iter()
+        .copied()
+        .chain(config.transforms.keys().map(String::as_str))
+        .collect();
+
+    let transforms = config
+        .transforms
+        .iter()
+        .map(|(k, v)| {
+            let inputs = parse_inputs(&v.inputs, k, &upstream)?;
+
+            let kind = v.kind.as_deref().unwrap_or(k);
+            let component = transform::Registration::build(kind, v.config.clone())
+                .map_err(|e| Error::Transform(e, k.clone()))?;
+
+            Ok(Component {
+                name: k.clone(),
+
+                inputs,
+
+                component,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(sort_transforms)?;
+
+    let mut sinks: Vec<_> = config.sinks.into_iter().collect();
+    sinks.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let sinks = sinks
         .into_iter()
         .map(|(k, v)| {
-            let component =
-                sink::Registration::build(&k, v).map_err(|e| Error::Sink(e, k.clone()))?;
+            let inputs = match &v.inputs {
+                Some(inputs) => parse_inputs(inputs, &k, &upstream)?,
+                None => {
+                    let all: Vec<_> = source_names
+                        .iter()
+                        .map(|n| glob::Pattern::escape(n))
+                        .collect();
+                    parse_inputs(&all, &k, &upstream)?
+                }
+            };
+
+            let kind = v.kind.as_deref().unwrap_or(&k);
+            let mut component = sink::Registration::build(kind, v.config, config.retry.as_ref())
+                .map_err(|e| Error::Sink(e, k.clone()))?;
+
+            if let Some(buffer) = v.buffer {
+                let default_path = state_dir.as_ref().map(|d| d.join("buffer").join(&k));
+                component =
+                    Box::new(Buffered::new(component, buffer, default_path).map_err(|e| {
+                        Error::Sink(sink::Error::Config(e, kind.to_string()), k.clone())
+                    })?);
+            }
 
             Ok(Component {
                 name: k.clone(),
 
+                inputs,
+
                 component,
             })
         })
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(Topology {
+        dates: config.dates,
+        state,
+        report_path: config.report.map(PathBuf::from),
+        workers: config.workers,
         data_sources,
+        transforms,
         sinks,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::{sort_transforms, Error};
+    use crate::{
+        point::Points,
+        topology::{Component, Inputs},
+        transform::{Transform, TransformResult},
+    };
+
+    struct Identity;
+
+    impl Transform for Identity {
+        fn transform(&self, points: Points) -> TransformResult<Points> {
+            Ok(points)
+        }
+    }
+
+    fn order(transforms: &[(&str, &[&str])]) -> Result<Vec<String>, Error> {
+        let transforms = transforms
+            .iter()
+            .map(|(name, inputs)| Component {
+                name: name.to_string(),
+                inputs: Inputs::parse(inputs).unwrap(),
+                component: Box::new(Identity) as Box<dyn Transform>,
+            })
+            .collect();
+
+        sort_transforms(transforms).map(|t| t.into_iter().map(|t| t.name).collect())
+    }
+
+    #[test]
+    fn test_sort_transforms() {
+        assert_eq!(
+            order(&[("a", &["b"]), ("b", &["eco2mix"])]).unwrap(),
+            ["b", "a"]
+        );
+        assert_eq!(order(&[("a", &["*"]), ("b", &["*"])]).unwrap(), ["a", "b"]);
+        assert_eq!(order(&[("a", &["b"]), ("b", &["*"])]).unwrap(), ["b", "a"]);
+        assert!(matches!(
+            order(&[("a", &["b"]), ("b", &["a"])]),
+            Err(Error::Cycle(_))
+        ));
+    }
+}
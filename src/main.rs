@@ -1,18 +1,26 @@
 use main_error::MainResult;
 mod config;
 mod point;
+mod pool;
+mod report;
+mod retry;
+mod schedule;
 mod sink;
 mod source;
+mod state;
 mod topology;
+mod transform;
 
 fn main() -> MainResult {
     tracing_subscriber::fmt::init();
 
     let config_file = std::env::args()
-        .skip(1)
-        .next()
+        .nth(1)
         .expect("usage crawler config_file.toml");
-    topology::run(config::read(config_file)?)?;
+    let outcome = topology::run(config::read(config_file)?)?;
 
-    Ok(())
+    match outcome.exit_code() {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
 }
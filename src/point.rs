@@ -20,6 +20,17 @@ pub enum Value {
     String(String),
 }
 
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{i}"),
+            Value::Float(v) => write!(f, "{v}"),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::String(s) => write!(f, "{s}"),
+        }
+    }
+}
+
 impl From<i8> for Value {
     fn from(val: i8) -> Self {
         Self::Integer(val as i64)
@@ -74,7 +85,7 @@ impl From<f64> for Value {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Point {
     pub name: String,
 
@@ -88,11 +99,6 @@ pub struct Point {
 pub struct PointBuilder(Point);
 
 impl PointBuilder {
-    pub fn tag(mut self, key: String, value: String) -> Self {
-        self.0.tags.insert(key, value);
-        self
-    }
-
     pub fn field(mut self, key: impl AsRef<str>, value: Value) -> Self {
         self.0.fields.insert(key.as_ref().to_string(), value);
         self
@@ -126,7 +132,7 @@ impl Point {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Points(Vec<Point>);
 
 impl From<Vec<Point>> for Points {
@@ -176,7 +182,16 @@ impl Points {
         self.0.append(&mut other.0);
     }
 
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Point> {
         self.0.iter()
     }
+
+    /// Splits the points into batches of at most `size` points
+    pub fn chunks(&self, size: usize) -> impl Iterator<Item = Points> + '_ {
+        self.0.chunks(size).map(|chunk| Points(chunk.to_vec()))
+    }
 }
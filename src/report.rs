@@ -0,0 +1,263 @@
+use std::{path::Path, time::Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentKind {
+    Source,
+
+    Transform,
+
+    Sink,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Success,
+
+    Failure,
+
+    /// The component did not run, e.g a sink when all its inputs failed
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ComponentReport {
+    pub name: String,
+
+    pub kind: ComponentKind,
+
+    pub status: Status,
+
+    pub points: usize,
+
+    pub duration_ms: u64,
+
+    /// Error followed by all its sources, outermost first
+    pub errors: Vec<String>,
+}
+
+/// Overall outcome of a run, mapped to the process exit code
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+
+    /// Some components failed but points were still delivered
+    Partial,
+
+    /// Nothing could be delivered
+    Failure,
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::Failure => 1,
+            Outcome::Partial => 2,
+        }
+    }
+
+    /// Combines the outcomes of two runs, a mix of outcomes being a partial failure
+    pub fn combine(self, other: Outcome) -> Outcome {
+        match (self, other) {
+            (a, b) if a == b => a,
+            _ => Outcome::Partial,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Report {
+    pub components: Vec<ComponentReport>,
+}
+
+fn error_chain(error: &dyn std::error::Error) -> Vec<String> {
+    let mut chain = vec![error.to_string()];
+    let mut source = error.source();
+
+    while let Some(e) = source {
+        chain.push(e.to_string());
+        source = e.source();
+    }
+
+    chain
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` on behalf of a component and records its status, duration and number
+    /// of points, returning the result of `f` if it succeeded
+    pub fn record<T>(
+        &mut self,
+        name: impl Into<String>,
+        kind: ComponentKind,
+        points: impl FnOnce(&T) -> usize,
+        f: impl FnOnce() -> Result<T, Box<dyn std::error::Error>>,
+    ) -> Option<T> {
+        let start = Instant::now();
+        let result = f();
+        let duration_ms = start.elapsed().as_millis() as u64;
+
+        let (status, points, errors, value) = match result {
+            Ok(value) => (Status::Success, points(&value), Vec::new(), Some(value)),
+            Err(e) => (Status::Failure, 0, error_chain(e.as_ref()), None),
+        };
+
+        self.components.push(ComponentReport {
+            name: name.into(),
+            kind,
+            status,
+            points,
+            duration_ms,
+            errors,
+        });
+
+        value
+    }
+
+    pub fn skip(&mut self, name: impl Into<String>, kind: ComponentKind) {
+        self.components.push(ComponentReport {
+            name: name.into(),
+            kind,
+            status: Status::Skipped,
+            points: 0,
+            duration_ms: 0,
+            errors: Vec::new(),
+        });
+    }
+
+    pub fn merge_with(&mut self, mut other: Report) {
+        self.components.append(&mut other.components);
+    }
+
+    pub fn status(&self, kind: ComponentKind, name: &str) -> Option<Status> {
+        self.components
+            .iter()
+            .find(|c| c.kind == kind && c.name == name)
+            .map(|c| c.status)
+    }
+
+    fn all_failed(&self, kind: ComponentKind) -> bool {
+        let mut components = self.components.iter().filter(|c| c.kind == kind).peekable();
+
+        components.peek().is_some() && components.all(|c| c.status != Status::Success)
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        if self.components.iter().all(|c| c.status == Status::Success) {
+            Outcome::Success
+        } else if self.all_failed(ComponentKind::Source) || self.all_failed(ComponentKind::Sink) {
+            Outcome::Failure
+        } else {
+            Outcome::Partial
+        }
+    }
+
+    pub fn log(&self) {
+        for c in &self.components {
+            match c.status {
+                Status::Success => info!(
+                    component = c.name.as_str(),
+                    kind = ?c.kind,
+                    points = c.points,
+                    duration_ms = c.duration_ms,
+                    "component succeeded"
+                ),
+                Status::Failure => error!(
+                    component = c.name.as_str(),
+                    kind = ?c.kind,
+                    duration_ms = c.duration_ms,
+                    errors = ?c.errors,
+                    "component failed"
+                ),
+                Status::Skipped => warn!(
+                    component = c.name.as_str(),
+                    kind = ?c.kind,
+                    "component skipped"
+                ),
+            }
+        }
+
+        info!(outcome = ?self.outcome(), "run completed");
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ComponentKind, Outcome, Report};
+
+    fn report(sources: &[bool], sinks: &[bool]) -> Report {
+        let mut report = Report::new();
+
+        for (kind, results) in [
+            (ComponentKind::Source, sources),
+            (ComponentKind::Sink, sinks),
+        ] {
+            for (i, ok) in results.iter().enumerate() {
+                report.record(
+                    format!("{i}"),
+                    kind,
+                    |_| 1,
+                    || {
+                        if *ok {
+                            Ok(())
+                        } else {
+                            Err("boom".into())
+                        }
+                    },
+                );
+            }
+        }
+
+        report
+    }
+
+    #[test]
+    fn test_outcome() {
+        assert_eq!(report(&[true, true], &[true]).outcome(), Outcome::Success);
+        assert_eq!(report(&[true, false], &[true]).outcome(), Outcome::Partial);
+        assert_eq!(report(&[true], &[true, false]).outcome(), Outcome::Partial);
+        assert_eq!(report(&[false, false], &[true]).outcome(), Outcome::Failure);
+        assert_eq!(report(&[true, true], &[false]).outcome(), Outcome::Failure);
+    }
+
+    #[test]
+    fn test_combine() {
+        assert_eq!(Outcome::Success.combine(Outcome::Success), Outcome::Success);
+        assert_eq!(Outcome::Failure.combine(Outcome::Failure), Outcome::Failure);
+        assert_eq!(Outcome::Success.combine(Outcome::Failure), Outcome::Partial);
+        assert_eq!(Outcome::Partial.combine(Outcome::Success), Outcome::Partial);
+    }
+
+    #[test]
+    fn test_error_chain() {
+        let mut report = Report::new();
+        let error = std::io::Error::other("inner");
+
+        report.record(
+            "source",
+            ComponentKind::Source,
+            |_: &()| 0,
+            || Err(crate::source::Error::Config(error.into(), "rte".to_string()).into()),
+        );
+
+        assert_eq!(
+            report.components[0].errors,
+            vec!["invalid configuration for rte: inner", "inner"]
+        );
+    }
+}
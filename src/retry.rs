@@ -0,0 +1,270 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{blocking::Response, header::RETRY_AFTER, StatusCode};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+const DEFAULT_RETRY_ON: [u16; 6] = [408, 429, 500, 502, 503, 504];
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid {1}: {0}")]
+    Duration(#[source] humantime::DurationError, &'static str),
+
+    #[error("invalid status code {0}")]
+    StatusCode(u16),
+
+    #[error("max_attempts must be at least 1")]
+    MaxAttempts,
+}
+
+/// Retry settings, configured globally under `[retry]` and overridable per component with a
+/// `retry` table, the component settings taking precedence
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RetryConfig {
+    pub max_attempts: Option<u32>,
+
+    pub base_delay: Option<String>,
+
+    pub max_delay: Option<String>,
+
+    pub jitter: Option<bool>,
+
+    /// HTTP status codes worth retrying
+    pub retry_on: Option<Vec<u16>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+
+    base_delay: Duration,
+
+    max_delay: Duration,
+
+    jitter: bool,
+
+    retry_on: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: true,
+            retry_on: DEFAULT_RETRY_ON
+                .iter()
+                .filter_map(|c| StatusCode::from_u16(*c).ok())
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<RetryConfig> for RetryPolicy {
+    type Error = Error;
+
+    fn try_from(config: RetryConfig) -> Result<Self, Self::Error> {
+        let parse = |s: Option<String>, default, name| match s {
+            Some(s) => humantime::parse_duration(&s).map_err(|e| Error::Duration(e, name)),
+            None => Ok(default),
+        };
+
+        let max_attempts = config.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS);
+        if max_attempts == 0 {
+            return Err(Error::MaxAttempts);
+        }
+
+        let retry_on = match config.retry_on {
+            Some(codes) => codes
+                .into_iter()
+                .map(|c| StatusCode::from_u16(c).map_err(|_| Error::StatusCode(c)))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => RetryPolicy::default().retry_on,
+        };
+
+        Ok(Self {
+            max_attempts,
+            base_delay: parse(config.base_delay, DEFAULT_BASE_DELAY, "base_delay")?,
+            max_delay: parse(config.max_delay, DEFAULT_MAX_DELAY, "max_delay")?,
+            jitter: config.jitter.unwrap_or(true),
+            retry_on,
+        })
+    }
+}
+
+/// Parses a `Retry-After` header, either a number of seconds or an HTTP date
+fn retry_after(response: &Response, now: DateTime<Utc>) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    DateTime::parse_from_rfc2822(value).ok().map(|date| {
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default()
+    })
+}
+
+impl RetryPolicy {
+    /// Exponential backoff delay before the given retry, starting at 1
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+
+        if self.jitter {
+            delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+        } else {
+            delay
+        }
+    }
+
+    /// Whether a request failed for a transient reason, other errors like invalid requests or
+    /// bodies failing every attempt the same way
+    fn is_retryable(error: &reqwest::Error) -> bool {
+        error.is_timeout() || error.is_connect()
+    }
+
+    /// Sends a request built by `send` until it succeeds, fails with a non retryable error
+    /// or the maximum number of attempts is reached. The last response is returned as is,
+    /// leaving non-success status codes to the caller
+    pub fn send(
+        &self,
+        what: &str,
+        send: impl Fn() -> reqwest::Result<Response>,
+    ) -> reqwest::Result<Response> {
+        let mut attempt = 1;
+
+        loop {
+            let result = send();
+
+            let delay = match &result {
+                _ if attempt >= self.max_attempts => return result,
+                Ok(response) if self.retry_on.contains(&response.status()) => {
+                    let delay = retry_after(response, Utc::now())
+                        .map(|d| d.min(self.max_delay))
+                        .unwrap_or_else(|| self.backoff(attempt));
+
+                    warn!(
+                        attempt,
+                        max_attempts = self.max_attempts,
+                        delay_ms = delay.as_millis() as u64,
+                        status = response.status().as_u16(),
+                        "{what} failed, retrying"
+                    );
+                    delay
+                }
+                Err(e) if Self::is_retryable(e) => {
+                    let delay = self.backoff(attempt);
+
+                    warn!(
+                        attempt,
+                        max_attempts = self.max_attempts,
+                        delay_ms = delay.as_millis() as u64,
+                        "{what} failed, retrying: {e}"
+                    );
+                    delay
+                }
+                _ => return result,
+            };
+
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+/// Fills the `retry` table of a component configuration with the global retry settings it
+/// does not override
+pub fn merge(config: &mut toml::Value, global: Option<&toml::Value>) {
+    let (table, global) = match (config.as_table_mut(), global.and_then(|g| g.as_table())) {
+        (Some(table), Some(global)) => (table, global),
+        _ => return,
+    };
+
+    match table.get_mut("retry").and_then(|r| r.as_table_mut()) {
+        Some(retry) => {
+            for (k, v) in global {
+                retry.entry(k.clone()).or_insert_with(|| v.clone());
+            }
+        }
+        None => {
+            table.insert("retry".to_string(), toml::Value::Table(global.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{merge, RetryConfig, RetryPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::try_from(RetryConfig {
+            base_delay: Some("100ms".to_string()),
+            max_delay: Some("1s".to_string()),
+            jitter: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(64), Duration::from_secs(1));
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        let delay = policy.backoff(3);
+        assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let invalid = [
+            RetryConfig {
+                max_attempts: Some(0),
+                ..Default::default()
+            },
+            RetryConfig {
+                base_delay: Some("soon".to_string()),
+                ..Default::default()
+            },
+            RetryConfig {
+                retry_on: Some(vec![42]),
+                ..Default::default()
+            },
+        ];
+
+        for config in invalid {
+            assert!(RetryPolicy::try_from(config).is_err());
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let global: toml::Value = toml::from_str("max_attempts = 5\njitter = false").unwrap();
+
+        let mut config: toml::Value = toml::from_str("token = \"x\"").unwrap();
+        merge(&mut config, Some(&global));
+        assert_eq!(config["retry"]["max_attempts"].as_integer(), Some(5));
+
+        let mut config: toml::Value = toml::from_str("[retry]\nmax_attempts = 2").unwrap();
+        merge(&mut config, Some(&global));
+        assert_eq!(config["retry"]["max_attempts"].as_integer(), Some(2));
+        assert_eq!(config["retry"]["jitter"].as_bool(), Some(false));
+    }
+}
@@ -0,0 +1,111 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid interval {1}: {0}")]
+    Interval(#[source] humantime::DurationError, String),
+
+    #[error("interval must be greater than zero")]
+    ZeroInterval,
+
+    #[error("invalid cron expression {1}: {0}")]
+    Cron(#[source] cron::error::Error, String),
+}
+
+/// Raw schedule as found in the `schedule` key of a source, either
+/// `schedule = { interval = "15m" }` or `schedule = { cron = "0 0 * * * *" }`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleConfig {
+    Interval(String),
+
+    Cron(String),
+}
+
+impl ScheduleConfig {
+    pub fn parse(&self) -> Result<Schedule, Error> {
+        match self {
+            ScheduleConfig::Interval(s) => {
+                let interval = humantime::parse_duration(s)
+                    .map_err(|e| Error::Interval(e, s.clone()))
+                    .and_then(|d| Duration::from_std(d).map_err(|_| Error::ZeroInterval))?;
+
+                if interval <= Duration::zero() {
+                    return Err(Error::ZeroInterval);
+                }
+
+                Ok(Schedule::Interval(interval))
+            }
+            ScheduleConfig::Cron(s) => cron::Schedule::from_str(s)
+                .map(|c| Schedule::Cron(Box::new(c)))
+                .map_err(|e| Error::Cron(e, s.clone())),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Schedule {
+    Interval(Duration),
+
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// Returns the first time the schedule fires once started at `start`, intervals firing
+    /// right away and cron expressions at their first matching time
+    pub fn first(&self, start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Interval(_) => Some(start),
+            Schedule::Cron(cron) => cron.after(&start).next(),
+        }
+    }
+
+    /// Returns the first time strictly after `after` at which the schedule fires
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Interval(interval) => Some(after + *interval),
+            Schedule::Cron(cron) => cron.after(&after).next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Schedule, ScheduleConfig};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn test_interval() {
+        let schedule = ScheduleConfig::Interval("15m".to_string()).parse().unwrap();
+        let now = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(schedule.first(now), Some(now));
+        assert_eq!(schedule.next_after(now), Some(now + Duration::minutes(15)));
+        assert!(ScheduleConfig::Interval("0s".to_string()).parse().is_err());
+        assert!(ScheduleConfig::Interval("soon".to_string())
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_cron() {
+        let schedule = ScheduleConfig::Cron("0 0 * * * *".to_string())
+            .parse()
+            .unwrap();
+        let now = Utc.with_ymd_and_hms(2022, 10, 1, 12, 30, 0).unwrap();
+
+        assert!(matches!(schedule, Schedule::Cron(_)));
+        assert_eq!(schedule.first(now), schedule.next_after(now));
+        assert_eq!(
+            schedule.next_after(now),
+            Some(Utc.with_ymd_and_hms(2022, 10, 1, 13, 0, 0).unwrap())
+        );
+        assert!(ScheduleConfig::Cron("every hour".to_string())
+            .parse()
+            .is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use reqwest::{blocking::RequestBuilder, header::AUTHORIZATION};
+
+use super::SinkResult;
+
+/// Authentication of the requests of the HTTP based sinks
+pub enum Auth {
+    None,
+
+    Basic {
+        username: String,
+        password: String,
+    },
+
+    Bearer(String),
+
+    /// Elasticsearch API key, sent as an `ApiKey` authorization header
+    ApiKey(String),
+}
+
+impl Auth {
+    /// Authentication from either a `username` and `password` or a `token`, `option` being the
+    /// name of the token option in errors
+    pub fn new(
+        username: Option<String>,
+        password: Option<String>,
+        token: Option<Auth>,
+        option: &str,
+    ) -> SinkResult<Self> {
+        match (username, password, token) {
+            (None, None, None) => Ok(Auth::None),
+            (Some(username), password, None) => Ok(Auth::Basic {
+                username,
+                password: password.unwrap_or_default(),
+            }),
+            (None, None, Some(token)) => Ok(token),
+            _ => Err(
+                format!("authentication requires either a username and password or {option}")
+                    .into(),
+            ),
+        }
+    }
+
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Auth::None => request,
+            Auth::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Auth::Bearer(token) => request.bearer_auth(token),
+            Auth::ApiKey(key) => request.header(AUTHORIZATION, format!("ApiKey {key}")),
+        }
+    }
+}
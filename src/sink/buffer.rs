@@ -0,0 +1,299 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, field, warn};
+
+use crate::point::Points;
+
+use super::{Sink, SinkResult};
+
+const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("error accessing buffer {1}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+
+    #[error("invalid buffered points {1}: {0}")]
+    Json(#[source] serde_json::Error, PathBuf),
+
+    #[error("points of {0} bytes do not fit in a buffer of {1} bytes")]
+    TooLarge(u64, u64),
+
+    #[error("{0} (failed to buffer points: {1})")]
+    Lost(#[source] Box<dyn std::error::Error>, Box<Error>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BufferConfig {
+    /// Directory holding undelivered points, defaults to `<state_dir>/buffer/<sink>`
+    pub path: Option<String>,
+
+    /// Maximum size of the buffer on disk, the oldest points being evicted first
+    pub max_bytes: Option<u64>,
+}
+
+/// On-disk write-ahead buffer of the points a sink failed to deliver, one JSON file per
+/// batch, named so that files sort from oldest to newest
+struct DiskBuffer {
+    path: PathBuf,
+
+    max_bytes: u64,
+
+    sequence: AtomicU64,
+}
+
+impl DiskBuffer {
+    fn open(path: PathBuf, max_bytes: u64) -> Result<Self, Error> {
+        fs::create_dir_all(&path).map_err(|e| Error::Io(e, path.clone()))?;
+
+        Ok(Self {
+            path,
+            max_bytes,
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    /// Buffered batches, oldest first, with their size
+    fn batches(&self) -> Result<Vec<(PathBuf, u64)>, Error> {
+        let mut batches = fs::read_dir(&self.path)
+            .map_err(|e| Error::Io(e, self.path.clone()))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|e| e == "json"))
+            .map(|entry| {
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                (entry.path(), size)
+            })
+            .collect::<Vec<_>>();
+
+        batches.sort();
+        Ok(batches)
+    }
+
+    fn push(&self, points: &Points) -> Result<(), Error> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let path = self
+            .path
+            .join(format!("{timestamp:020}-{sequence:06}.json"));
+
+        let content = serde_json::to_vec(points).map_err(|e| Error::Json(e, path.clone()))?;
+        let size = content.len() as u64;
+
+        if size > self.max_bytes {
+            return Err(Error::TooLarge(size, self.max_bytes));
+        }
+
+        let batches = self.batches()?;
+        let mut total: u64 = batches.iter().map(|(_, size)| size).sum::<u64>() + size;
+
+        for (evicted, evicted_size) in batches {
+            if total <= self.max_bytes {
+                break;
+            }
+
+            warn!(
+                path = field::display(evicted.display()),
+                "buffer is full, evicting oldest points"
+            );
+            fs::remove_file(&evicted).map_err(|e| Error::Io(e, evicted.clone()))?;
+            total -= evicted_size;
+        }
+
+        fs::write(&path, content).map_err(|e| Error::Io(e, path.clone()))
+    }
+
+    fn read(path: &Path) -> Result<Points, Error> {
+        let content = fs::read(path).map_err(|e| Error::Io(e, path.to_path_buf()))?;
+        serde_json::from_slice(&content).map_err(|e| Error::Json(e, path.to_path_buf()))
+    }
+}
+
+/// Sink wrapper replaying buffered points before delivering new ones, and buffering the points
+/// the inner sink fails to deliver
+pub struct Buffered {
+    inner: Box<dyn Sink>,
+
+    buffer: DiskBuffer,
+}
+
+impl Buffered {
+    pub fn new(
+        inner: Box<dyn Sink>,
+        config: BufferConfig,
+        default_path: Option<PathBuf>,
+    ) -> SinkResult<Self> {
+        let path = config
+            .path
+            .map(PathBuf::from)
+            .or(default_path)
+            .ok_or("buffer requires a path or a state_dir")?;
+
+        let buffer = DiskBuffer::open(path, config.max_bytes.unwrap_or(DEFAULT_MAX_BYTES))?;
+        Ok(Self { inner, buffer })
+    }
+
+    /// Replays buffered batches oldest first, stopping at the first failure
+    fn replay(&self) -> SinkResult<()> {
+        for (path, _) in self.buffer.batches()? {
+            let points = match DiskBuffer::read(&path) {
+                Ok(points) => points,
+                Err(e) => {
+                    warn!("discarding unreadable buffered points: {e}");
+                    fs::remove_file(&path).map_err(|e| Error::Io(e, path.clone()))?;
+                    continue;
+                }
+            };
+
+            debug!(
+                path = field::display(path.display()),
+                points = points.len(),
+                "replaying buffered points"
+            );
+
+            self.inner.sink(&points)?;
+            fs::remove_file(&path).map_err(|e| Error::Io(e, path.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Buffers points the inner sink failed to deliver. Once buffered, points are as good as
+    /// delivered: failing would keep the sources from advancing their checkpoint and the same
+    /// points would be both collected again and replayed
+    fn buffer(&self, points: &Points, error: Box<dyn std::error::Error>) -> SinkResult<()> {
+        match self.buffer.push(points) {
+            Ok(()) => {
+                warn!(points = points.len(), "{error}, points were buffered");
+                Ok(())
+            }
+            Err(e) => Err(Error::Lost(error, Box::new(e)).into()),
+        }
+    }
+}
+
+impl Sink for Buffered {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        if let Err(e) = self.replay() {
+            return self.buffer(points, e);
+        }
+
+        match self.inner.sink(points) {
+            Ok(()) => Ok(()),
+            Err(e) => self.buffer(points, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::{BufferConfig, Buffered};
+    use crate::{
+        point::{Point, Points, Value},
+        sink::{Sink, SinkResult},
+        value,
+    };
+
+    struct Flaky {
+        down: Rc<Cell<bool>>,
+
+        delivered: Rc<Cell<usize>>,
+    }
+
+    impl Sink for Flaky {
+        fn sink(&self, points: &Points) -> SinkResult<()> {
+            if self.down.get() {
+                return Err("sink is down".into());
+            }
+
+            self.delivered.set(self.delivered.get() + points.len());
+            Ok(())
+        }
+    }
+
+    fn points(n: usize) -> Points {
+        (0..n)
+            .map(|i| {
+                Point::builder("test")
+                    .field("value", value!(i as i64))
+                    .build()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_buffer_replay() {
+        let dir = std::env::temp_dir().join(format!("photon-buffer-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let down = Rc::new(Cell::new(true));
+        let delivered = Rc::new(Cell::new(0));
+        let config = BufferConfig {
+            path: None,
+            max_bytes: None,
+        };
+        let sink = Buffered::new(
+            Box::new(Flaky {
+                down: down.clone(),
+                delivered: delivered.clone(),
+            }),
+            config,
+            Some(dir.clone()),
+        )
+        .unwrap();
+
+        sink.sink(&points(2)).unwrap();
+        sink.sink(&points(3)).unwrap();
+        assert_eq!(delivered.get(), 0);
+        assert_eq!(sink.buffer.batches().unwrap().len(), 2);
+
+        down.set(false);
+        sink.sink(&points(1)).unwrap();
+
+        assert_eq!(delivered.get(), 6);
+        assert!(sink.buffer.batches().unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_buffer_eviction() {
+        let dir = std::env::temp_dir().join(format!("photon-eviction-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let size = serde_json::to_vec(&points(1)).unwrap().len() as u64;
+        let config = BufferConfig {
+            path: Some(dir.to_string_lossy().to_string()),
+            max_bytes: Some(size * 2),
+        };
+        let sink = Buffered::new(
+            Box::new(Flaky {
+                down: Rc::new(Cell::new(true)),
+                delivered: Rc::new(Cell::new(0)),
+            }),
+            config,
+            None,
+        )
+        .unwrap();
+
+        for _ in 0..3 {
+            sink.sink(&points(1)).unwrap();
+        }
+
+        let batches = sink.buffer.batches().unwrap();
+        assert_eq!(batches.len(), 2);
+
+        assert!(sink.sink(&points(3)).is_err());
+        assert_eq!(sink.buffer.batches().unwrap().len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,828 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::{
+    point::{Point, Points, Value},
+    retry::{RetryConfig, RetryPolicy},
+    sink::Registration,
+};
+
+use super::{Sink, SinkConfig, SinkResult};
+
+const DEFAULT_DATABASE: &str = "default";
+const DEFAULT_BATCH_SIZE: usize = 10000;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to send request")]
+    Request(#[source] reqwest::Error),
+
+    #[error("query on measurement {0} resulted in a non-success status code {1} with error: {2}")]
+    Query(String, StatusCode, String),
+
+    #[error("failed to parse the columns of measurement {1}")]
+    Columns(#[source] serde_json::Error, String),
+
+    #[error("column {0} of measurement {1} is both a tag and a field")]
+    DuplicateColumn(String, String),
+
+    #[error("column {0} of measurement {1} has an unsupported type {2}")]
+    UnsupportedType(String, String, String),
+
+    #[error("value {0} of field {1} does not fit in a {2} column")]
+    Conversion(Value, String, &'static str),
+
+    #[error("table {0} does not exist and create_tables is disabled")]
+    MissingTable(String),
+
+    #[error("timestamp {0} of a {1} point does not fit in a nanosecond timestamp")]
+    Timestamp(DateTime<Utc>, String),
+}
+
+/// Format of the inserted rows
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Format {
+    #[default]
+    #[serde(rename = "JSONEachRow", alias = "jsoneachrow")]
+    JsonEachRow,
+
+    #[serde(rename = "RowBinary", alias = "rowbinary")]
+    RowBinary,
+}
+
+/// Engine of the created tables, `ReplacingMergeTree` replacing rows with the same time and
+/// tags when parts are merged
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Engine {
+    #[serde(rename = "MergeTree", alias = "mergetree")]
+    MergeTree,
+
+    #[default]
+    #[serde(rename = "ReplacingMergeTree", alias = "replacingmergetree")]
+    ReplacingMergeTree,
+}
+
+/// ClickHouse type of a field column, following the same widening rules as the postgres sink
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColumnType {
+    Int64,
+
+    Float64,
+
+    Bool,
+
+    String,
+}
+
+impl ColumnType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Integer(_) => ColumnType::Int64,
+            Value::Float(_) => ColumnType::Float64,
+            Value::Boolean(_) => ColumnType::Bool,
+            Value::String(_) => ColumnType::String,
+        }
+    }
+
+    fn merge(self, other: ColumnType) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Int64, ColumnType::Float64) | (ColumnType::Float64, ColumnType::Int64) => {
+                ColumnType::Float64
+            }
+            _ => ColumnType::String,
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            ColumnType::Int64 => "Int64",
+            ColumnType::Float64 => "Float64",
+            ColumnType::Bool => "Bool",
+            ColumnType::String => "String",
+        }
+    }
+
+    fn from_sql(data_type: &str) -> Option<Self> {
+        match data_type {
+            "Nullable(Int64)" => Some(ColumnType::Int64),
+            "Nullable(Float64)" => Some(ColumnType::Float64),
+            "Nullable(Bool)" => Some(ColumnType::Bool),
+            "Nullable(String)" => Some(ColumnType::String),
+            _ => None,
+        }
+    }
+
+    /// Converts a field value to the JSON value of this column type, missing values being null
+    fn json(&self, name: &str, value: Option<&Value>) -> Result<serde_json::Value, Error> {
+        Ok(match (self, value) {
+            (_, None) => serde_json::Value::Null,
+            (ColumnType::Int64, Some(Value::Integer(i))) => json!(i),
+            (ColumnType::Float64, Some(Value::Integer(i))) => json!(*i as f64),
+            (ColumnType::Float64, Some(Value::Float(f))) => json!(f),
+            (ColumnType::Bool, Some(Value::Boolean(b))) => json!(b),
+            (ColumnType::String, Some(v)) => json!(v.to_string()),
+            (_, Some(v)) => return Err(self.conversion(name, v)),
+        })
+    }
+
+    /// Appends a field value in the RowBinary encoding of a `Nullable` column of this type
+    fn binary(&self, out: &mut Vec<u8>, name: &str, value: Option<&Value>) -> Result<(), Error> {
+        match (self, value) {
+            (_, None) => out.push(1),
+            (ColumnType::Int64, Some(Value::Integer(i))) => {
+                out.push(0);
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+            (ColumnType::Float64, Some(Value::Integer(i))) => {
+                out.push(0);
+                out.extend_from_slice(&(*i as f64).to_le_bytes());
+            }
+            (ColumnType::Float64, Some(Value::Float(f))) => {
+                out.push(0);
+                out.extend_from_slice(&f.to_le_bytes());
+            }
+            (ColumnType::Bool, Some(Value::Boolean(b))) => {
+                out.push(0);
+                out.push(*b as u8);
+            }
+            (ColumnType::String, Some(v)) => {
+                out.push(0);
+                binary_string(out, &v.to_string());
+            }
+            (_, Some(v)) => return Err(self.conversion(name, v)),
+        }
+
+        Ok(())
+    }
+
+    fn conversion(&self, name: &str, value: &Value) -> Error {
+        Error::Conversion(value.clone(), name.to_string(), self.sql())
+    }
+}
+
+/// Appends a string in the RowBinary encoding, prefixed by its LEB128 length
+fn binary_string(out: &mut Vec<u8>, value: &str) {
+    let mut len = value.len();
+    while len >= 0x80 {
+        out.push((len as u8 & 0x7f) | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn quote(identifier: &str) -> String {
+    format!("`{}`", identifier.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Columns of a measurement table: a `time` column, tags as non-null strings defaulting to an
+/// empty string that make up the sorting key along with the time, and nullable fields
+#[derive(Default, Debug)]
+struct Table {
+    /// Columns of the sorting key, in order
+    key: Vec<String>,
+
+    tags: BTreeSet<String>,
+
+    fields: BTreeMap<String, ColumnType>,
+}
+
+fn order_by(key: &[String]) -> String {
+    key.iter().map(|c| quote(c)).collect::<Vec<_>>().join(", ")
+}
+
+/// Builds the creation of `table` with a `time` column and the `tags` columns, sorted by `key`
+fn create_statement(
+    table: &str,
+    tags: &BTreeSet<String>,
+    key: &[String],
+    engine: Engine,
+) -> String {
+    let columns = std::iter::once("time DateTime64(9, 'UTC')".to_string())
+        .chain(
+            tags.iter()
+                .map(|t| format!("{} LowCardinality(String) DEFAULT ''", quote(t))),
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+    let engine = match engine {
+        Engine::MergeTree => "MergeTree",
+        Engine::ReplacingMergeTree => "ReplacingMergeTree",
+    };
+
+    format!(
+        "CREATE TABLE IF NOT EXISTS {table} ({columns}) ENGINE = {engine} \
+         PARTITION BY toYYYYMM(time) ORDER BY ({})",
+        order_by(key)
+    )
+}
+
+/// Builds the addition of a `tag` column to `table`, appended to its sorting `key`
+fn add_tag_statement(table: &str, tag: &str, key: &[String]) -> String {
+    let mut key = key.to_vec();
+    key.push(tag.to_string());
+
+    format!(
+        "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {} LowCardinality(String) DEFAULT '', \
+         MODIFY ORDER BY ({})",
+        quote(tag),
+        order_by(&key)
+    )
+}
+
+fn add_field_statement(table: &str, field: &str, column_type: ColumnType) -> String {
+    format!(
+        "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {} Nullable({})",
+        quote(field),
+        column_type.sql()
+    )
+}
+
+#[derive(Deserialize)]
+struct Column {
+    name: String,
+
+    #[serde(rename = "type")]
+    data_type: String,
+
+    is_in_sorting_key: u8,
+}
+
+struct ClickHouse {
+    url: Url,
+
+    username: Option<String>,
+
+    password: Option<String>,
+
+    database: String,
+
+    format: Format,
+
+    engine: Engine,
+
+    batch_size: usize,
+
+    create_tables: bool,
+
+    retry: RetryPolicy,
+
+    /// Known tables by measurement, reloaded after any failure
+    tables: Mutex<HashMap<String, Table>>,
+}
+
+impl ClickHouse {
+    fn table_name(&self, measurement: &str) -> String {
+        format!("{}.{}", quote(&self.database), quote(measurement))
+    }
+
+    /// Runs a query, `data` being sent as the body of `INSERT` queries
+    fn query(
+        &self,
+        client: &reqwest::blocking::Client,
+        measurement: &str,
+        query: &str,
+        data: Option<&[u8]>,
+    ) -> Result<String, Error> {
+        let response = self
+            .retry
+            .send("clickhouse query", || {
+                let request = match data {
+                    Some(data) => client
+                        .post(self.url.clone())
+                        .query(&[("query", query)])
+                        .body(data.to_vec()),
+                    None => client.post(self.url.clone()).body(query.to_string()),
+                };
+
+                match &self.username {
+                    Some(username) => request
+                        .header("X-ClickHouse-User", username)
+                        .header("X-ClickHouse-Key", self.password.as_deref().unwrap_or("")),
+                    None => request,
+                }
+                .send()
+            })
+            .map_err(Error::Request)?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .unwrap_or("Failed to retrieve response text".to_string());
+        if !status.is_success() {
+            return Err(Error::Query(measurement.to_string(), status, body));
+        }
+
+        Ok(body)
+    }
+
+    fn load_table(
+        &self,
+        client: &reqwest::blocking::Client,
+        measurement: &str,
+    ) -> Result<Option<Table>, Error> {
+        let filter = format!(
+            "database = {} AND table = {}",
+            literal(&self.database),
+            literal(measurement)
+        );
+        let columns = self.query(
+            client,
+            measurement,
+            &format!(
+                "SELECT name, type, is_in_sorting_key FROM system.columns \
+                 WHERE {filter} ORDER BY position FORMAT JSONEachRow"
+            ),
+            None,
+        )?;
+
+        if columns.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.query(
+            client,
+            measurement,
+            &format!(
+                "SELECT sorting_key FROM system.tables WHERE {} FORMAT TSVRaw",
+                filter.replace("table =", "name =")
+            ),
+            None,
+        )?;
+
+        let mut table = Table {
+            key: key
+                .trim()
+                .split(", ")
+                .map(|c| c.trim_matches('`').to_string())
+                .collect(),
+            ..Default::default()
+        };
+
+        for line in columns.lines() {
+            let column: Column = serde_json::from_str(line)
+                .map_err(|e| Error::Columns(e, measurement.to_string()))?;
+
+            if column.name == "time" {
+                continue;
+            }
+
+            if column.is_in_sorting_key == 1 {
+                table.tags.insert(column.name);
+            } else {
+                let column_type = ColumnType::from_sql(&column.data_type).ok_or_else(|| {
+                    Error::UnsupportedType(
+                        column.name.clone(),
+                        measurement.to_string(),
+                        column.data_type,
+                    )
+                })?;
+                table.fields.insert(column.name, column_type);
+            }
+        }
+
+        Ok(Some(table))
+    }
+
+    fn create_table(
+        &self,
+        client: &reqwest::blocking::Client,
+        measurement: &str,
+        points: &[&Point],
+    ) -> Result<Table, Error> {
+        let name = self.table_name(measurement);
+        info!(table = name.as_str(), "creating table");
+
+        // Tags are created along with the table as adding them to the sorting key later is only
+        // possible after the time
+        let tags: BTreeSet<String> = points.iter().flat_map(|p| p.tags.keys().cloned()).collect();
+        let key: Vec<String> = tags
+            .iter()
+            .cloned()
+            .chain(std::iter::once("time".to_string()))
+            .collect();
+
+        self.query(
+            client,
+            measurement,
+            &create_statement(&name, &tags, &key, self.engine),
+            None,
+        )?;
+
+        Ok(Table {
+            key,
+            tags,
+            fields: BTreeMap::new(),
+        })
+    }
+
+    /// Makes sure the table of a measurement has a column for every tag and field of `points`.
+    /// Without `create_tables`, the table must exist and missing columns are not written
+    fn prepare(
+        &self,
+        client: &reqwest::blocking::Client,
+        tables: &mut HashMap<String, Table>,
+        measurement: &str,
+        points: &[&Point],
+    ) -> Result<(), Error> {
+        let mut table = match tables.remove(measurement) {
+            Some(table) => table,
+            None => match self.load_table(client, measurement)? {
+                Some(table) => table,
+                None if self.create_tables => self.create_table(client, measurement, points)?,
+                None => return Err(Error::MissingTable(self.table_name(measurement))),
+            },
+        };
+
+        let name = self.table_name(measurement);
+        let mut missing = BTreeSet::new();
+
+        for tag in points.iter().flat_map(|p| p.tags.keys()) {
+            if table.tags.contains(tag) {
+                continue;
+            }
+
+            if table.fields.contains_key(tag) {
+                return Err(Error::DuplicateColumn(tag.clone(), measurement.to_string()));
+            }
+
+            if !self.create_tables {
+                missing.insert(tag);
+                continue;
+            }
+
+            self.query(
+                client,
+                measurement,
+                &add_tag_statement(&name, tag, &table.key),
+                None,
+            )?;
+
+            table.key.push(tag.clone());
+            table.tags.insert(tag.clone());
+        }
+
+        for (field, value) in points.iter().flat_map(|p| &p.fields) {
+            if table.tags.contains(field) {
+                return Err(Error::DuplicateColumn(
+                    field.clone(),
+                    measurement.to_string(),
+                ));
+            }
+
+            if table.fields.contains_key(field) {
+                continue;
+            }
+
+            if !self.create_tables {
+                missing.insert(field);
+                continue;
+            }
+
+            let column_type = points
+                .iter()
+                .filter_map(|p| p.fields.get(field))
+                .map(ColumnType::of)
+                .fold(ColumnType::of(value), ColumnType::merge);
+
+            self.query(
+                client,
+                measurement,
+                &add_field_statement(&name, field, column_type),
+                None,
+            )?;
+
+            table.fields.insert(field.clone(), column_type);
+        }
+
+        if !missing.is_empty() {
+            warn!(
+                table = name.as_str(),
+                "skipping tags and fields without a column: {}",
+                missing.into_iter().cloned().collect::<Vec<_>>().join(", ")
+            );
+        }
+
+        tables.insert(measurement.to_string(), table);
+        Ok(())
+    }
+
+    /// Encodes the rows of the points in the `time`, tags then fields column order
+    fn rows(
+        &self,
+        table: &Table,
+        fields: &[(&String, ColumnType)],
+        points: &[&Point],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+
+        for point in points {
+            let time = point.timestamp.unwrap_or(now);
+
+            match self.format {
+                Format::JsonEachRow => {
+                    let mut row = serde_json::Map::new();
+                    row.insert(
+                        "time".to_string(),
+                        json!(time.format("%Y-%m-%d %H:%M:%S%.9f").to_string()),
+                    );
+                    for tag in &table.tags {
+                        row.insert(tag.clone(), json!(point.tags.get(tag).map_or("", |t| t)));
+                    }
+                    for (field, column_type) in fields {
+                        row.insert(
+                            field.to_string(),
+                            column_type.json(field, point.fields.get(*field))?,
+                        );
+                    }
+
+                    out.extend(serde_json::Value::Object(row).to_string().into_bytes());
+                    out.push(b'\n');
+                }
+                Format::RowBinary => {
+                    let nanos = time
+                        .timestamp_nanos_opt()
+                        .ok_or_else(|| Error::Timestamp(time, point.name.clone()))?;
+                    out.extend_from_slice(&nanos.to_le_bytes());
+                    for tag in &table.tags {
+                        binary_string(&mut out, point.tags.get(tag).map_or("", |t| t));
+                    }
+                    for (field, column_type) in fields {
+                        column_type.binary(&mut out, field, point.fields.get(*field))?;
+                    }
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn write(
+        &self,
+        client: &reqwest::blocking::Client,
+        table: &Table,
+        measurement: &str,
+        points: &[&Point],
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let fields: Vec<(&String, ColumnType)> = points
+            .iter()
+            .flat_map(|p| p.fields.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|f| table.fields.get(f).map(|t| (f, *t)))
+            .collect();
+
+        let columns = std::iter::once("time")
+            .chain(table.tags.iter().map(String::as_str))
+            .chain(fields.iter().map(|(f, _)| f.as_str()))
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let format = match self.format {
+            Format::JsonEachRow => "JSONEachRow",
+            Format::RowBinary => "RowBinary",
+        };
+        let query = format!(
+            "INSERT INTO {} ({columns}) FORMAT {format}",
+            self.table_name(measurement)
+        );
+
+        for chunk in points.chunks(self.batch_size) {
+            let rows = self.rows(table, &fields, chunk, now)?;
+
+            debug!(measurement, rows = chunk.len(), "inserting rows");
+            self.query(client, measurement, &query, Some(&rows))?;
+        }
+
+        Ok(())
+    }
+
+    fn sink(&self, tables: &mut HashMap<String, Table>, points: &Points) -> Result<(), Error> {
+        let client = reqwest::blocking::Client::new();
+        let now = Utc::now();
+
+        let mut measurements: BTreeMap<&str, Vec<&Point>> = BTreeMap::new();
+        for point in points.iter() {
+            measurements.entry(&point.name).or_default().push(point);
+        }
+
+        for (measurement, points) in measurements {
+            self.prepare(&client, tables, measurement, &points)?;
+            self.write(&client, &tables[measurement], measurement, &points, now)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for ClickHouse {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let mut tables = self.tables.lock().expect("poisoned lock");
+
+        let result = ClickHouse::sink(self, &mut tables, points);
+        if result.is_err() {
+            tables.clear();
+        }
+
+        Ok(result?)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// URL of the HTTP interface, e.g. `http://localhost:8123`
+    url: String,
+
+    username: Option<String>,
+
+    password: Option<String>,
+
+    database: Option<String>,
+
+    format: Option<Format>,
+
+    engine: Option<Engine>,
+
+    /// Maximum number of rows per insert
+    batch_size: Option<usize>,
+
+    /// Create the tables and their columns as needed
+    create_tables: Option<bool>,
+
+    retry: Option<RetryConfig>,
+}
+
+impl SinkConfig for Config {
+    const RETRY: bool = true;
+
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        Ok(Box::new(ClickHouse {
+            url: self.url.parse()?,
+
+            username: self.username,
+
+            password: self.password,
+
+            database: self.database.unwrap_or(DEFAULT_DATABASE.to_string()),
+
+            format: self.format.unwrap_or_default(),
+
+            engine: self.engine.unwrap_or_default(),
+
+            batch_size: super::batch_size(self.batch_size, DEFAULT_BATCH_SIZE)?,
+
+            create_tables: self.create_tables.unwrap_or(true),
+
+            retry: RetryPolicy::try_from(self.retry.unwrap_or_default())?,
+
+            tables: Mutex::new(HashMap::new()),
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("clickhouse")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        add_field_statement, add_tag_statement, create_statement, ClickHouse, ColumnType, Engine,
+        Error, Format, Table,
+    };
+    use crate::{
+        point::{Point, Value},
+        retry::{RetryConfig, RetryPolicy},
+    };
+    use chrono::{TimeZone, Utc};
+
+    fn clickhouse(format: Format) -> ClickHouse {
+        ClickHouse {
+            url: "http://localhost:8123".parse().unwrap(),
+            username: None,
+            password: None,
+            database: "default".to_string(),
+            format,
+            engine: Default::default(),
+            batch_size: 1,
+            create_tables: true,
+            retry: RetryPolicy::try_from(RetryConfig::default()).unwrap(),
+            tables: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_statements() {
+        let key = vec!["source".to_string(), "time".to_string()];
+
+        assert_eq!(
+            create_statement(
+                "`default`.`eco2mix`",
+                &["source".to_string()].into(),
+                &key,
+                Engine::ReplacingMergeTree
+            ),
+            "CREATE TABLE IF NOT EXISTS `default`.`eco2mix` (time DateTime64(9, 'UTC'), \
+             `source` LowCardinality(String) DEFAULT '') ENGINE = ReplacingMergeTree \
+             PARTITION BY toYYYYMM(time) ORDER BY (`source`, `time`)"
+        );
+        assert_eq!(
+            add_tag_statement("`default`.`eco2mix`", "region", &key),
+            "ALTER TABLE `default`.`eco2mix` ADD COLUMN IF NOT EXISTS `region` \
+             LowCardinality(String) DEFAULT '', MODIFY ORDER BY (`source`, `time`, `region`)"
+        );
+        assert_eq!(
+            add_field_statement("`default`.`eco2mix`", "nuclear", ColumnType::Float64),
+            "ALTER TABLE `default`.`eco2mix` ADD COLUMN IF NOT EXISTS `nuclear` \
+             Nullable(Float64)"
+        );
+    }
+
+    #[test]
+    fn test_rows() {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(30000))
+            .field("level", Value::String("green".to_string()))
+            .timestamp(Utc.timestamp_nanos(1_664_625_600_000_000_001))
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "eco2mix".to_string());
+
+        let table = Table {
+            key: vec!["source".to_string(), "time".to_string()],
+            tags: ["source".to_string(), "region".to_string()].into(),
+            fields: [
+                ("nuclear".to_string(), ColumnType::Float64),
+                ("level".to_string(), ColumnType::String),
+            ]
+            .into(),
+        };
+        let nuclear = "nuclear".to_string();
+        let level = "level".to_string();
+        let fields = [
+            (&level, ColumnType::String),
+            (&nuclear, ColumnType::Float64),
+        ];
+
+        let json = clickhouse(Format::JsonEachRow)
+            .rows(&table, &fields, &[&point], Utc::now())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"level\":\"green\",\"nuclear\":30000.0,\"region\":\"\",\"source\":\"eco2mix\",\
+             \"time\":\"2022-10-01 12:00:00.000000001\"}\n"
+        );
+
+        let binary = clickhouse(Format::RowBinary)
+            .rows(&table, &fields, &[&point], Utc::now())
+            .unwrap();
+        let mut expected = 1_664_625_600_000_000_001i64.to_le_bytes().to_vec();
+        expected.extend_from_slice(b"\x00\x07eco2mix\x00\x05green\x00");
+        expected.extend_from_slice(&30000f64.to_le_bytes());
+        assert_eq!(binary, expected);
+
+        point.timestamp = Some(Utc.with_ymd_and_hms(2300, 1, 1, 0, 0, 0).unwrap());
+        assert!(clickhouse(Format::RowBinary)
+            .rows(&table, &fields, &[&point], Utc::now())
+            .is_err());
+    }
+
+    #[test]
+    fn test_tag_on_field_column() {
+        let mut point = Point::builder("eco2mix")
+            .field("level", Value::String("green".to_string()))
+            .build();
+        point.tags.insert("nuclear".to_string(), "high".to_string());
+
+        let table = Table {
+            key: vec!["time".to_string()],
+            tags: Default::default(),
+            fields: [("nuclear".to_string(), ColumnType::Float64)].into(),
+        };
+        let mut tables = [("eco2mix".to_string(), table)].into();
+
+        // Fails before any statement is sent
+        let client = reqwest::blocking::Client::new();
+        assert!(matches!(
+            clickhouse(Format::RowBinary).prepare(&client, &mut tables, "eco2mix", &[&point]),
+            Err(Error::DuplicateColumn(..))
+        ));
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::point::{Point, Points, Value};
+
+use super::influxdb::{self, Precision};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("failed to write points")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to serialize points to JSON")]
+    Json(#[from] serde_json::Error),
+
+    #[error("failed to serialize points to CSV")]
+    Csv(#[from] csv::Error),
+
+    #[error("failed to serialize points to the line protocol")]
+    Line(#[from] influxdb::TimestampError),
+}
+
+/// Output format of the sinks writing points as text
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// Pretty-printed JSON array
+    Json,
+
+    /// One JSON point per line
+    Ndjson,
+
+    /// InfluxDB line protocol
+    Influx,
+
+    Csv,
+
+    /// Human-readable aligned columns
+    Table,
+}
+
+/// Fields of a point as a JSON object of plain JSON values, unlike the serialization of `Value`
+/// which tags them with their type
+pub fn json_fields(point: &Point) -> BTreeMap<&String, serde_json::Value> {
+    point
+        .fields
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::Integer(i) => json!(i),
+                Value::Float(f) => json!(f),
+                Value::Boolean(b) => json!(b),
+                Value::String(s) => json!(s),
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+/// Columns of the tabular codecs: name and timestamp followed by every tag and field found in
+/// the points, tags first, sorted by name
+pub struct Columns(Vec<String>);
+
+impl Columns {
+    pub fn new(points: &Points) -> Self {
+        let mut tags = BTreeSet::new();
+        let mut fields = BTreeSet::new();
+
+        for point in points.iter() {
+            tags.extend(point.tags.keys());
+            fields.extend(point.fields.keys());
+        }
+
+        Self(tags.into_iter().chain(fields).cloned().collect())
+    }
+
+    /// Reads the columns from the header line of an output of the given codec
+    pub fn parse(codec: Codec, header: &str) -> Option<Self> {
+        let names: Vec<String> = match codec {
+            Codec::Csv => csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(header.as_bytes())
+                .records()
+                .next()?
+                .ok()?
+                .iter()
+                .map(String::from)
+                .collect(),
+            Codec::Table => header
+                .split("  ")
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect(),
+            _ => return None,
+        };
+
+        match names.as_slice() {
+            [name, timestamp, columns @ ..] if name == "name" && timestamp == "timestamp" => {
+                Some(Self(columns.to_vec()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Tags and fields of the points that are not part of these columns
+    pub fn missing(&self, points: &Points) -> Vec<String> {
+        let missing: BTreeSet<&String> = points
+            .iter()
+            .flat_map(|p| p.tags.keys().chain(p.fields.keys()))
+            .filter(|name| !self.0.contains(name))
+            .collect();
+
+        missing.into_iter().cloned().collect()
+    }
+
+    fn header(&self) -> Vec<String> {
+        ["name", "timestamp"]
+            .iter()
+            .map(|s| s.to_string())
+            .chain(self.0.iter().cloned())
+            .collect()
+    }
+
+    fn row(&self, point: &Point) -> Vec<String> {
+        let timestamp = point.timestamp.map(|t| t.to_rfc3339()).unwrap_or_default();
+        let values = self.0.iter().map(|c| match point.tags.get(c) {
+            Some(tag) => tag.clone(),
+            None => point
+                .fields
+                .get(c)
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+        });
+
+        [point.name.clone(), timestamp]
+            .into_iter()
+            .chain(values)
+            .collect()
+    }
+}
+
+impl Codec {
+    /// Whether the codec writes a header naming its columns
+    pub fn is_tabular(&self) -> bool {
+        matches!(self, Codec::Csv | Codec::Table)
+    }
+
+    /// Writes the points to `out`, `header` telling whether the column names of the tabular
+    /// codecs should be written first, which is not wanted when appending to existing output
+    pub fn encode(&self, points: &Points, out: &mut impl Write, header: bool) -> Result<(), Error> {
+        self.encode_with(points, out, &Columns::new(points), header)
+    }
+
+    /// Writes the points to `out` like `encode`, the tabular codecs writing the given columns
+    pub fn encode_with(
+        &self,
+        points: &Points,
+        out: &mut impl Write,
+        columns: &Columns,
+        header: bool,
+    ) -> Result<(), Error> {
+        match self {
+            Codec::Json => {
+                serde_json::to_writer_pretty(&mut *out, points)?;
+                writeln!(out)?;
+            }
+            Codec::Ndjson => {
+                for point in points.iter() {
+                    serde_json::to_writer(&mut *out, point)?;
+                    writeln!(out)?;
+                }
+            }
+            Codec::Influx => {
+                let now = Utc::now();
+                for point in points.iter() {
+                    writeln!(out, "{}", influxdb::line(point, now, Precision::Ns)?)?;
+                }
+            }
+            Codec::Csv => {
+                let mut writer = csv::Writer::from_writer(out);
+
+                if header {
+                    writer.write_record(columns.header())?;
+                }
+                for point in points.iter() {
+                    writer.write_record(columns.row(point))?;
+                }
+                writer.flush()?;
+            }
+            Codec::Table => {
+                let rows: Vec<Vec<String>> = std::iter::once(columns.header())
+                    .chain(points.iter().map(|p| columns.row(p)))
+                    .collect();
+
+                let mut widths = vec![0; rows[0].len()];
+                for row in &rows {
+                    for (width, cell) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(cell.chars().count());
+                    }
+                }
+
+                for row in rows.iter().skip(if header { 0 } else { 1 }) {
+                    let line = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, width)| format!("{cell:width$}"))
+                        .collect::<Vec<_>>()
+                        .join("  ");
+                    writeln!(out, "{}", line.trim_end())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Codec, Columns};
+    use crate::{
+        point::{Point, Points, Value},
+        value,
+    };
+    use chrono::{TimeZone, Utc};
+
+    fn points() -> Points {
+        let timestamp = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+
+        let mut nuclear = Point::builder("eco2mix")
+            .field("nuclear", value!(30000i64))
+            .timestamp(timestamp)
+            .build();
+        nuclear
+            .tags
+            .insert("source".to_string(), "eco2mix".to_string());
+
+        let solar = Point::builder("eco2mix")
+            .field("solar", value!(1500.5))
+            .timestamp(timestamp)
+            .build();
+
+        vec![nuclear, solar].into()
+    }
+
+    fn encode(codec: Codec) -> String {
+        let mut out = Vec::new();
+        codec.encode(&points(), &mut out, true).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_codecs() {
+        assert_eq!(encode(Codec::Ndjson).lines().count(), 2);
+        assert_eq!(
+            encode(Codec::Influx),
+            "eco2mix,source=eco2mix nuclear=30000 1664625600000000000\n\
+             eco2mix solar=1500.5 1664625600000000000\n"
+        );
+        assert_eq!(
+            encode(Codec::Csv),
+            "name,timestamp,source,nuclear,solar\n\
+             eco2mix,2022-10-01T12:00:00+00:00,eco2mix,30000,\n\
+             eco2mix,2022-10-01T12:00:00+00:00,,,1500.5\n"
+        );
+        assert_eq!(
+            encode(Codec::Table),
+            "name     timestamp                  source   nuclear  solar\n\
+             eco2mix  2022-10-01T12:00:00+00:00  eco2mix  30000\n\
+             eco2mix  2022-10-01T12:00:00+00:00                    1500.5\n"
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        for codec in [Codec::Csv, Codec::Table] {
+            let encoded = encode(codec);
+            let header = encoded.lines().next().unwrap();
+            let columns = Columns::parse(codec, header).unwrap();
+            assert!(columns.missing(&points()).is_empty());
+
+            let mut points = points();
+            points.tag_all("region", "FR");
+            assert_eq!(columns.missing(&points), vec!["region"]);
+        }
+
+        assert!(Columns::parse(Codec::Csv, "nuclear,solar").is_none());
+    }
+}
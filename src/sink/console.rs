@@ -2,25 +2,28 @@ use serde::{Deserialize, Serialize};
 
 use crate::{point::Points, sink::Registration};
 
-use super::{Sink, SinkConfig};
+use super::{codec::Codec, Sink, SinkConfig};
 
-struct Console;
+struct Console {
+    codec: Codec,
+}
 
 impl Sink for Console {
     fn sink(&self, points: &Points) -> super::SinkResult<()> {
-        println!("{}", serde_json::to_string_pretty(&points)?);
+        let stdout = std::io::stdout();
+        self.codec.encode(points, &mut stdout.lock(), true)?;
         Ok(())
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Config {
-    codec: String,
+    codec: Codec,
 }
 
 impl SinkConfig for Config {
     fn build(self) -> super::SinkResult<Box<dyn Sink>> {
-        Ok(Box::new(Console))
+        Ok(Box::new(Console { codec: self.codec }))
     }
 }
 
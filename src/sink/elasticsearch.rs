@@ -0,0 +1,562 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::CONTENT_TYPE, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::{
+    point::{Point, Points, Value},
+    retry::{RetryConfig, RetryPolicy},
+    sink::Registration,
+};
+
+use super::{auth::Auth, codec, Sink, SinkConfig, SinkResult};
+
+const DEFAULT_INDEX: &str = "photon-{measurement}-{date}";
+const DEFAULT_DATE_FORMAT: &str = "%Y.%m.%d";
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to send request")]
+    Request(#[source] reqwest::Error),
+
+    #[error("{0} resulted in a non-success status code {1} with error: {2}")]
+    Status(&'static str, StatusCode, String),
+
+    #[error("failed to parse the bulk response")]
+    Response(#[source] reqwest::Error),
+
+    #[error("{0} of {1} documents were rejected, first error: {2}")]
+    Bulk(usize, usize, String),
+
+    #[error("invalid url {0}, expected an http(s) url")]
+    Url(String),
+}
+
+/// Field type of the index template mappings, following the same widening rules as the
+/// columns of the parquet sink
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FieldType {
+    Long,
+
+    Double,
+
+    Boolean,
+
+    Keyword,
+}
+
+impl FieldType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Integer(_) => FieldType::Long,
+            Value::Float(_) => FieldType::Double,
+            Value::Boolean(_) => FieldType::Boolean,
+            Value::String(_) => FieldType::Keyword,
+        }
+    }
+
+    fn merge(self, other: FieldType) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (FieldType::Long, FieldType::Double) | (FieldType::Double, FieldType::Long) => {
+                FieldType::Double
+            }
+            _ => FieldType::Keyword,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Long => "long",
+            FieldType::Double => "double",
+            FieldType::Boolean => "boolean",
+            FieldType::Keyword => "keyword",
+        }
+    }
+}
+
+/// Mappings of the fields seen so far in the indices matching a pattern
+#[derive(Default)]
+struct Mappings {
+    tags: Vec<String>,
+
+    fields: BTreeMap<String, FieldType>,
+}
+
+impl Mappings {
+    /// Adds the tags and fields of a point, returning whether the mappings changed
+    fn update(&mut self, point: &Point) -> bool {
+        let mut changed = false;
+
+        for tag in point.tags.keys() {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+                self.tags.sort();
+                changed = true;
+            }
+        }
+
+        for (name, value) in &point.fields {
+            let field_type = FieldType::of(value);
+            let merged = self
+                .fields
+                .get(name)
+                .map_or(field_type, |t| t.merge(field_type));
+
+            if self.fields.insert(name.clone(), merged) != Some(merged) {
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Body of a composable index template applying the mappings to the indices matching
+    /// `pattern`
+    fn template(&self, pattern: &str) -> serde_json::Value {
+        let tags: serde_json::Map<String, serde_json::Value> = self
+            .tags
+            .iter()
+            .map(|tag| (tag.clone(), json!({"type": "keyword"})))
+            .collect();
+        let fields: serde_json::Map<String, serde_json::Value> = self
+            .fields
+            .iter()
+            .map(|(name, t)| (name.clone(), json!({"type": t.as_str()})))
+            .collect();
+
+        json!({
+            "index_patterns": [pattern],
+            "template": {
+                "mappings": {
+                    "properties": {
+                        "@timestamp": {"type": "date"},
+                        "measurement": {"type": "keyword"},
+                        "tags": {"properties": tags},
+                        "fields": {"properties": fields},
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Identifier of the document of a point, the 128-bit FNV-1a hash of its measurement, tags sorted
+/// by name and timestamp, so that indexing a point again overwrites its document
+fn id(point: &Point, timestamp: DateTime<Utc>) -> String {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    let tags: BTreeMap<&String, &String> = point.tags.iter().collect();
+    let timestamp = timestamp.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true);
+    let key = std::iter::once(point.name.as_str())
+        .chain(tags.into_iter().flat_map(|(k, v)| [k.as_str(), v.as_str()]))
+        .chain(std::iter::once(timestamp.as_str()))
+        .collect::<Vec<_>>()
+        .join("\0");
+
+    let hash = key
+        .bytes()
+        .fold(OFFSET, |hash, b| (hash ^ b as u128).wrapping_mul(PRIME));
+    format!("{hash:032x}")
+}
+
+fn document(point: &Point, now: DateTime<Utc>) -> serde_json::Value {
+    let fields = codec::json_fields(point);
+    let tags: BTreeMap<&String, &String> = point.tags.iter().collect();
+
+    json!({
+        "@timestamp": point.timestamp.unwrap_or(now).to_rfc3339(),
+        "measurement": point.name,
+        "tags": tags,
+        "fields": fields,
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkResponse {
+    errors: bool,
+
+    /// One entry per action, keyed by the action type
+    items: Vec<HashMap<String, BulkItem>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkItem {
+    #[serde(rename = "_index")]
+    index: String,
+
+    status: u16,
+
+    error: Option<BulkError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkError {
+    #[serde(rename = "type")]
+    kind: String,
+
+    reason: Option<String>,
+}
+
+/// Logs the rejected documents of a bulk response, failing with the first error
+fn check(response: &BulkResponse) -> Result<(), Error> {
+    if !response.errors {
+        return Ok(());
+    }
+
+    let errors: Vec<String> = response
+        .items
+        .iter()
+        .flat_map(|item| item.values())
+        .filter_map(|item| {
+            let error = item.error.as_ref()?;
+            let message = format!(
+                "{} ({}) in {}: {}",
+                error.kind,
+                item.status,
+                item.index,
+                error.reason.as_deref().unwrap_or("no reason given")
+            );
+            warn!("document rejected: {message}");
+            Some(message)
+        })
+        .collect();
+
+    match errors.first() {
+        Some(first) => Err(Error::Bulk(
+            errors.len(),
+            response.items.len(),
+            first.clone(),
+        )),
+        None => Ok(()),
+    }
+}
+
+struct Elasticsearch {
+    url: Url,
+
+    /// Index name template, see `Elasticsearch::index`
+    index: String,
+
+    date_format: String,
+
+    auth: Auth,
+
+    batch_size: usize,
+
+    retry: RetryPolicy,
+
+    /// Mappings of the index templates by index pattern, when templates are enabled
+    templates: Option<Mutex<HashMap<String, Mappings>>>,
+}
+
+impl Elasticsearch {
+    /// Renders the index of a point, replacing `{measurement}`, `{source}` and `{date}`, or
+    /// its pattern when `date` is `None`
+    fn index(&self, point: &Point, date: Option<DateTime<Utc>>) -> String {
+        let source = point
+            .tags
+            .get("source")
+            .map(String::as_str)
+            .unwrap_or("unknown");
+        let date = date.map_or("*".to_string(), |d| d.format(&self.date_format).to_string());
+
+        self.index
+            .replace("{measurement}", &point.name)
+            .replace("{source}", source)
+            .replace("{date}", &date)
+            .to_lowercase()
+    }
+
+    fn send(
+        &self,
+        what: &'static str,
+        request: impl Fn() -> reqwest::blocking::RequestBuilder,
+    ) -> Result<reqwest::blocking::Response, Error> {
+        let response = self
+            .retry
+            .send(what, || self.auth.apply(request()).send())
+            .map_err(Error::Request)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response
+                .text()
+                .unwrap_or("Failed to retrieve response text".to_string());
+            return Err(Error::Status(what, status, body));
+        }
+
+        Ok(response)
+    }
+
+    /// Creates or updates the index templates of the indices of the points whose mappings
+    /// changed
+    fn update_templates(
+        &self,
+        client: &reqwest::blocking::Client,
+        points: &Points,
+    ) -> Result<(), Error> {
+        let templates = match &self.templates {
+            Some(templates) => templates,
+            None => return Ok(()),
+        };
+        let mut templates = templates.lock().expect("poisoned lock");
+
+        let mut changed = Vec::new();
+        for point in points.iter() {
+            let pattern = self.index(point, None);
+            if templates.entry(pattern.clone()).or_default().update(point)
+                && !changed.contains(&pattern)
+            {
+                changed.push(pattern);
+            }
+        }
+
+        for pattern in changed {
+            let name = pattern.replace('*', "").trim_matches('-').to_string();
+            let body = templates[&pattern].template(&pattern);
+            let url = self
+                .url
+                .join(&format!("_index_template/{name}"))
+                .expect("invalid url");
+
+            info!(template = name, pattern, "updating index template");
+            if let Err(e) = self.send("index template update", || {
+                client.put(url.clone()).json(&body)
+            }) {
+                // Updated again once the next points arrive
+                templates.remove(&pattern);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn bulk(&self, client: &reqwest::blocking::Client, points: &Points) -> Result<(), Error> {
+        let now = Utc::now();
+        let mut body = String::new();
+        for point in points.iter() {
+            let timestamp = point.timestamp.unwrap_or(now);
+            let action = json!({"index": {
+                "_index": self.index(point, Some(timestamp)),
+                "_id": id(point, timestamp),
+            }});
+            body.push_str(&action.to_string());
+            body.push('\n');
+            body.push_str(&document(point, now).to_string());
+            body.push('\n');
+        }
+
+        debug!(
+            documents = points.len(),
+            bytes = body.len(),
+            "sending bulk request"
+        );
+
+        let url = self.url.join("_bulk").expect("invalid url");
+        let response: BulkResponse = self
+            .send("bulk request", || {
+                client
+                    .post(url.clone())
+                    .header(CONTENT_TYPE, "application/x-ndjson")
+                    .body(body.clone())
+            })?
+            .json()
+            .map_err(Error::Response)?;
+
+        check(&response)
+    }
+}
+
+impl Sink for Elasticsearch {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let client = reqwest::blocking::Client::new();
+
+        self.update_templates(&client, points)?;
+        for batch in points.chunks(self.batch_size) {
+            self.bulk(&client, &batch)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// Base URL of the cluster, e.g. `http://localhost:9200`
+    url: String,
+
+    /// Index name template, see `Elasticsearch::index`
+    index: Option<String>,
+
+    /// Format of the `{date}` of the index names
+    date_format: Option<String>,
+
+    username: Option<String>,
+
+    password: Option<String>,
+
+    /// Base64-encoded API key
+    api_key: Option<String>,
+
+    /// Create index templates mapping the tags and fields of the points
+    index_template: Option<bool>,
+
+    /// Maximum number of documents per bulk request
+    batch_size: Option<usize>,
+
+    retry: Option<RetryConfig>,
+}
+
+impl SinkConfig for Config {
+    const RETRY: bool = true;
+
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let auth = Auth::new(
+            self.username,
+            self.password,
+            self.api_key.map(Auth::ApiKey),
+            "api_key",
+        )?;
+
+        // Joined URLs must not replace the last segment of the base URL
+        let mut url = self.url;
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+
+        // Relative URLs cannot be joined to URLs like `mailto:` ones
+        let url: Url = url.parse()?;
+        if url.cannot_be_a_base() {
+            return Err(Error::Url(url.to_string()).into());
+        }
+
+        Ok(Box::new(Elasticsearch {
+            url,
+
+            index: self.index.unwrap_or(DEFAULT_INDEX.to_string()),
+
+            date_format: self.date_format.unwrap_or(DEFAULT_DATE_FORMAT.to_string()),
+
+            auth,
+
+            batch_size: super::batch_size(self.batch_size, DEFAULT_BATCH_SIZE)?,
+
+            retry: RetryPolicy::try_from(self.retry.unwrap_or_default())?,
+
+            templates: self
+                .index_template
+                .unwrap_or(false)
+                .then(|| Mutex::new(HashMap::new())),
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("elasticsearch")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, id, BulkResponse, Config, Mappings};
+    use crate::{
+        point::{Point, Value},
+        sink::SinkConfig,
+    };
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    #[test]
+    fn test_id() {
+        let timestamp = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+        let point = |tags: &[(&str, &str)]| {
+            let mut point = Point::builder("eco2mix")
+                .field("nuclear", Value::Integer(30000))
+                .build();
+            for (k, v) in tags {
+                point.tags.insert(k.to_string(), v.to_string());
+            }
+            point
+        };
+
+        let first = id(&point(&[("source", "rte"), ("area", "FR")]), timestamp);
+        assert_eq!(first.len(), 32);
+        assert_eq!(
+            first,
+            id(&point(&[("area", "FR"), ("source", "rte")]), timestamp)
+        );
+        assert_ne!(first, id(&point(&[("source", "rte")]), timestamp));
+        assert_ne!(
+            first,
+            id(
+                &point(&[("source", "rte"), ("area", "FR")]),
+                timestamp + chrono::Duration::hours(1)
+            )
+        );
+    }
+
+    #[test]
+    fn test_url() {
+        let config: Config = toml::from_str("url = \"mailto:photon@example.com\"").unwrap();
+        assert!(config.build().is_err());
+    }
+
+    #[test]
+    fn test_mappings() {
+        let mut mappings = Mappings::default();
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(30000))
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "eco2mix".to_string());
+
+        assert!(mappings.update(&point));
+        assert!(!mappings.update(&point));
+
+        point
+            .fields
+            .insert("nuclear".to_string(), Value::Float(0.5));
+        assert!(mappings.update(&point));
+
+        assert_eq!(
+            mappings.template("photon-eco2mix-*")["template"]["mappings"]["properties"],
+            json!({
+                "@timestamp": {"type": "date"},
+                "measurement": {"type": "keyword"},
+                "tags": {"properties": {"source": {"type": "keyword"}}},
+                "fields": {"properties": {"nuclear": {"type": "double"}}},
+            })
+        );
+    }
+
+    #[test]
+    fn test_bulk_errors() {
+        let response: BulkResponse = serde_json::from_str(
+            r#"{"took": 3, "errors": true, "items": [
+                {"index": {"_index": "photon-eco2mix-2022.10.01", "status": 201}},
+                {"index": {"_index": "photon-eco2mix-2022.10.01", "status": 400, "error": {
+                    "type": "mapper_parsing_exception",
+                    "reason": "failed to parse field [fields.nuclear] of type [long]"
+                }}}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            check(&response).unwrap_err().to_string(),
+            "1 of 2 documents were rejected, first error: mapper_parsing_exception (400) in \
+             photon-eco2mix-2022.10.01: failed to parse field [fields.nuclear] of type [long]"
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, field, info};
+
+use crate::{
+    point::{Point, Points},
+    sink::Registration,
+};
+
+use super::{
+    codec::{Codec, Columns},
+    Sink, SinkConfig, SinkResult,
+};
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("error writing {1}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+
+    #[error("error writing {1}: {0}")]
+    Codec(#[source] super::codec::Error, PathBuf),
+
+    #[error("invalid rotation interval {1}: {0}")]
+    Interval(#[source] humantime::DurationError, String),
+
+    #[error("error reading the points of {1}: {0}")]
+    Json(#[source] serde_json::Error, PathBuf),
+
+    #[error("missing or invalid header in {0}")]
+    Header(PathBuf),
+
+    #[error("columns {} are missing from the header of {}", .0.join(", "), .1.display())]
+    Columns(Vec<String>, PathBuf),
+
+    #[error("the json codec writes a single array per file and cannot be used with append=true")]
+    JsonAppend,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RotateConfig {
+    /// Size from which a file is rotated before writing to it
+    max_bytes: Option<u64>,
+
+    /// Age from which a file is rotated before writing to it
+    interval: Option<String>,
+
+    /// Compress rotated files
+    gzip: Option<bool>,
+}
+
+struct Rotation {
+    max_bytes: Option<u64>,
+
+    interval: Option<Duration>,
+
+    gzip: bool,
+}
+
+impl Rotation {
+    fn is_due(&self, path: &Path) -> bool {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+
+        let too_big = self.max_bytes.is_some_and(|max| metadata.len() >= max);
+        let too_old = self.interval.is_some_and(|interval| {
+            metadata
+                .created()
+                .or_else(|_| metadata.modified())
+                .ok()
+                .and_then(|created| SystemTime::now().duration_since(created).ok())
+                .is_some_and(|age| age >= interval)
+        });
+
+        too_big || too_old
+    }
+
+    /// Moves `path` aside under a timestamped name, compressing it when configured to
+    fn rotate(&self, path: &Path) -> Result<(), Error> {
+        let suffix = Utc::now().format("%Y%m%dT%H%M%S%.3f");
+        let rotated = PathBuf::from(format!("{}.{suffix}", path.display()));
+
+        info!(
+            path = field::display(path.display()),
+            rotated = field::display(rotated.display()),
+            "rotating file"
+        );
+
+        fs::rename(path, &rotated).map_err(|e| Error::Io(e, path.to_path_buf()))?;
+
+        if self.gzip {
+            let compressed = PathBuf::from(format!("{}.gz", rotated.display()));
+            let io_error = |e| Error::Io(e, compressed.clone());
+
+            let mut input = File::open(&rotated).map_err(io_error)?;
+            let output = File::create(&compressed).map_err(io_error)?;
+            let mut encoder = GzEncoder::new(output, Compression::default());
+
+            std::io::copy(&mut input, &mut encoder)
+                .and_then(|_| encoder.finish())
+                .map_err(io_error)?;
+            fs::remove_file(&rotated).map_err(|e| Error::Io(e, rotated.clone()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders the path template of a point, replacing `{source}`, `{measurement}` and `{date}`
+fn render(template: &str, point: &Point, now: DateTime<Utc>) -> PathBuf {
+    let source = point
+        .tags
+        .get("source")
+        .map(String::as_str)
+        .unwrap_or("unknown");
+    let date = point
+        .timestamp
+        .unwrap_or(now)
+        .format("%Y-%m-%d")
+        .to_string();
+
+    PathBuf::from(
+        template
+            .replace("{source}", source)
+            .replace("{measurement}", &point.name)
+            .replace("{date}", &date),
+    )
+}
+
+/// Reads the first line of a file
+fn read_header(path: &Path) -> Option<String> {
+    let mut line = String::new();
+    BufReader::new(File::open(path).ok()?)
+        .read_line(&mut line)
+        .ok()?;
+
+    Some(line)
+}
+
+/// Reads back the points of a file written with the json codec, a missing file having none
+fn read_points(path: &Path) -> Result<Points, Error> {
+    match File::open(path) {
+        Ok(file) => serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| Error::Json(e, path.to_path_buf())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Points::new()),
+        Err(e) => Err(Error::Io(e, path.to_path_buf())),
+    }
+}
+
+struct FileSink {
+    path: String,
+
+    codec: Codec,
+
+    append: bool,
+
+    rotation: Option<Rotation>,
+
+    /// Files written since the sink was built, only those are appended to when `append` is off
+    written: Mutex<HashSet<PathBuf>>,
+}
+
+impl FileSink {
+    fn write(&self, path: &Path, points: &Points) -> Result<(), Error> {
+        if let Some(rotation) = &self.rotation {
+            if rotation.is_due(path) {
+                rotation.rotate(path)?;
+            }
+        }
+
+        let first_write = self
+            .written
+            .lock()
+            .expect("poisoned lock")
+            .insert(path.to_path_buf());
+
+        // A json file holds a single array, the points written to it earlier in the run are
+        // read back so that the whole array is written again
+        let rewrite = self.codec == Codec::Json && !first_write;
+        let truncate = (first_write && !self.append) || rewrite;
+        let points = if rewrite {
+            let mut existing = read_points(path)?;
+            existing.merge_with(points.clone());
+            Cow::Owned(existing)
+        } else {
+            Cow::Borrowed(points)
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::Io(e, parent.to_path_buf()))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(!truncate)
+            .write(true)
+            .truncate(truncate)
+            .open(path)
+            .map_err(|e| Error::Io(e, path.to_path_buf()))?;
+        let header = file.metadata().map(|m| m.len() == 0).unwrap_or(true);
+
+        // Tabular files keep the columns of their header, points with other tags or fields
+        // being rejected rather than written under the wrong columns
+        let columns = if self.codec.is_tabular() && !header {
+            let columns = read_header(path)
+                .and_then(|line| Columns::parse(self.codec, &line))
+                .ok_or_else(|| Error::Header(path.to_path_buf()))?;
+
+            let missing = columns.missing(&points);
+            if !missing.is_empty() {
+                return Err(Error::Columns(missing, path.to_path_buf()));
+            }
+
+            columns
+        } else {
+            Columns::new(&points)
+        };
+
+        debug!(
+            path = field::display(path.display()),
+            points = points.len(),
+            "writing points"
+        );
+
+        let mut writer = BufWriter::new(file);
+        self.codec
+            .encode_with(&points, &mut writer, &columns, header)
+            .map_err(|e| Error::Codec(e, path.to_path_buf()))?;
+        writer.flush().map_err(|e| Error::Io(e, path.to_path_buf()))
+    }
+}
+
+impl Sink for FileSink {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let now = Utc::now();
+
+        let mut files: BTreeMap<PathBuf, Points> = BTreeMap::new();
+        for point in points.iter() {
+            files
+                .entry(render(&self.path, point, now))
+                .or_insert_with(Points::new)
+                .add(point.clone());
+        }
+
+        for (path, points) in files {
+            self.write(&path, &points)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// Path template, see `render`
+    path: String,
+
+    codec: Option<Codec>,
+
+    append: Option<bool>,
+
+    rotate: Option<RotateConfig>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let rotation = match self.rotate {
+            Some(rotate) => Some(Rotation {
+                max_bytes: rotate.max_bytes,
+                interval: rotate
+                    .interval
+                    .map(|s| humantime::parse_duration(&s).map_err(|e| Error::Interval(e, s)))
+                    .transpose()?,
+                gzip: rotate.gzip.unwrap_or(false),
+            }),
+            None => None,
+        };
+
+        let codec = self.codec.unwrap_or(Codec::Ndjson);
+        let append = match (codec, self.append) {
+            (Codec::Json, Some(true)) => return Err(Error::JsonAppend.into()),
+            (Codec::Json, None) => false,
+            (_, append) => append.unwrap_or(true),
+        };
+
+        Ok(Box::new(FileSink {
+            path: self.path,
+
+            codec,
+
+            append,
+
+            rotation,
+
+            written: Mutex::new(HashSet::new()),
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("file")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::{
+        point::{Point, Points, Value},
+        sink::SinkConfig,
+        value,
+    };
+    use chrono::{TimeZone, Utc};
+
+    fn points(source: &str, day: u32) -> Points {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", value!(30000i64))
+            .timestamp(Utc.with_ymd_and_hms(2022, 10, day, 12, 0, 0).unwrap())
+            .build();
+        point.tags.insert("source".to_string(), source.to_string());
+
+        vec![point].into()
+    }
+
+    #[test]
+    fn test_file_sink() {
+        let dir = std::env::temp_dir().join(format!("photon-file-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            path = "{}/{{source}}/{{date}}.csv"
+            codec = "csv"
+            rotate = {{ max_bytes = 80, gzip = true }}
+            "#,
+            dir.display()
+        ))
+        .unwrap();
+        let sink = config.build().unwrap();
+
+        let mut batch = points("eco2mix", 1);
+        batch.merge_with(points("eco2mix", 2));
+        batch.merge_with(points("ecowatt", 1));
+        sink.sink(&batch).unwrap();
+        sink.sink(&points("eco2mix", 1)).unwrap();
+
+        let content = std::fs::read_to_string(dir.join("eco2mix/2022-10-01.csv")).unwrap();
+        assert_eq!(
+            content,
+            "name,timestamp,source,nuclear\n\
+             eco2mix,2022-10-01T12:00:00+00:00,eco2mix,30000\n\
+             eco2mix,2022-10-01T12:00:00+00:00,eco2mix,30000\n"
+        );
+        assert!(dir.join("eco2mix/2022-10-02.csv").exists());
+        assert!(dir.join("ecowatt/2022-10-01.csv").exists());
+
+        // The file is now above max_bytes and gets rotated on the next write
+        sink.sink(&points("eco2mix", 1)).unwrap();
+
+        let rotated: Vec<_> = std::fs::read_dir(dir.join("eco2mix"))
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("2022-10-01.csv.") && name.ends_with(".gz"))
+            .collect();
+        assert_eq!(rotated.len(), 1);
+
+        let content = std::fs::read_to_string(dir.join("eco2mix/2022-10-01.csv")).unwrap();
+        assert_eq!(content.lines().count(), 2);
+
+        // Appending points with a field missing from the header fails
+        let mut solar = points("eco2mix", 1);
+        solar.tag_all("region", "FR");
+        assert!(sink.sink(&solar).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_rewrite() {
+        let dir = std::env::temp_dir().join(format!("photon-file-json-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let config: Config = toml::from_str(&format!(
+            "path = \"{}/{{date}}.json\"\ncodec = \"json\"",
+            dir.display()
+        ))
+        .unwrap();
+        let sink = config.build().unwrap();
+
+        sink.sink(&points("eco2mix", 1)).unwrap();
+        sink.sink(&points("ecowatt", 1)).unwrap();
+
+        let content = std::fs::read_to_string(dir.join("2022-10-01.json")).unwrap();
+        let points: Vec<serde_json::Value> = serde_json::from_str(&content).unwrap();
+        assert_eq!(points.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_append() {
+        let config: Config = toml::from_str("path = \"out.json\"\ncodec = \"json\"").unwrap();
+        assert!(config.build().is_ok());
+
+        let config: Config =
+            toml::from_str("path = \"out.json\"\ncodec = \"json\"\nappend = true").unwrap();
+        assert!(config.build().is_err());
+    }
+}
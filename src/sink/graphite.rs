@@ -0,0 +1,292 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    point::{Point, Points, Value},
+    sink::Registration,
+};
+
+use super::{tcp::LineWriter, Sink, SinkConfig, SinkResult};
+
+const DEFAULT_BATCH_SIZE: usize = 5000;
+const DEFAULT_TIMEOUT: &str = "10s";
+const DEFAULT_TEMPLATE: &str = "{measurement}.{tags}.{field}";
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("invalid timeout {1}: {0}")]
+    Duration(#[source] humantime::DurationError, String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    /// Metric paths rendered from the template
+    #[default]
+    Plaintext,
+
+    /// `<measurement>.<field>;<tag>=<value>` tagged series
+    Tagged,
+}
+
+/// Replaces the characters that would split a path segment, a tag or the line
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '.' | ';' | '=' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Replaces the characters not allowed in the value of a tag: whitespace splitting the line, `;`
+/// and a leading `~`
+fn tag_value(value: &str) -> String {
+    value
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            ';' => '_',
+            '~' if i == 0 => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+struct Graphite {
+    writer: LineWriter,
+
+    format: Format,
+
+    prefix: Option<String>,
+
+    template: String,
+
+    /// Order of the tags in paths, tags not listed following sorted by name
+    tag_order: Vec<String>,
+
+    batch_size: usize,
+}
+
+impl Graphite {
+    fn tags<'a>(&'a self, point: &'a Point) -> Vec<(&'a String, &'a String)> {
+        let mut tags: Vec<_> = point.tags.iter().collect();
+        tags.sort_by_key(|(name, _)| {
+            let position = self.tag_order.iter().position(|t| t == *name);
+            (position.unwrap_or(usize::MAX), *name)
+        });
+        tags
+    }
+
+    /// Renders the path of a field, replacing `{measurement}`, `{field}`, `{tags}`, the values of
+    /// the ordered tags, and `{tags.<name>}`. Empty segments, e.g. of missing tags, are removed
+    fn path(&self, point: &Point, field: &str) -> String {
+        let tags = self.tags(point);
+        let values: Vec<String> = tags.iter().map(|(_, v)| sanitize(v)).collect();
+
+        let mut path = self
+            .template
+            .replace("{measurement}", &sanitize(&point.name))
+            .replace("{field}", &sanitize(field))
+            .replace("{tags}", &values.join("."));
+        for (name, value) in tags {
+            path = path.replace(&format!("{{tags.{name}}}"), &sanitize(value));
+        }
+        while let Some(start) = path.find("{tags.") {
+            let end = path[start..]
+                .find('}')
+                .map_or(path.len(), |end| start + end + 1);
+            path.replace_range(start..end, "");
+        }
+
+        self.prefix
+            .iter()
+            .map(String::as_str)
+            .chain(path.split('.'))
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Name of a field in the tagged series syntax
+    fn tagged(&self, point: &Point, field: &str) -> String {
+        let name = self
+            .prefix
+            .iter()
+            .map(String::as_str)
+            .chain([point.name.as_str(), field])
+            .map(sanitize)
+            .collect::<Vec<_>>()
+            .join(".");
+
+        self.tags(point)
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .fold(name, |name, (k, v)| {
+                format!("{name};{}={}", sanitize(k), tag_value(v))
+            })
+    }
+
+    /// Lines of the numeric fields of a point, booleans being written as `0` or `1`
+    fn lines(&self, point: &Point, now: DateTime<Utc>) -> Vec<String> {
+        let timestamp = point.timestamp.unwrap_or(now).timestamp();
+
+        let mut fields: Vec<_> = point.fields.iter().collect();
+        fields.sort_by_key(|(name, _)| *name);
+
+        fields
+            .into_iter()
+            .filter_map(|(field, value)| {
+                let value = match value {
+                    Value::Integer(i) => i.to_string(),
+                    Value::Float(f) => f.to_string(),
+                    Value::Boolean(b) => (*b as u8).to_string(),
+                    Value::String(_) => return None,
+                };
+
+                let name = match self.format {
+                    Format::Plaintext => self.path(point, field),
+                    Format::Tagged => self.tagged(point, field),
+                };
+
+                Some(format!("{name} {value} {timestamp}"))
+            })
+            .collect()
+    }
+}
+
+impl Sink for Graphite {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let now = Utc::now();
+        let lines: Vec<String> = points.iter().flat_map(|p| self.lines(p, now)).collect();
+
+        self.writer.send(&lines, self.batch_size)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// `host:port` of the carbon plaintext listener, usually on port 2003
+    address: String,
+
+    tls: Option<bool>,
+
+    /// Timeout of the connection and of the writes
+    timeout: Option<String>,
+
+    format: Option<Format>,
+
+    prefix: Option<String>,
+
+    /// Path template of the plaintext format, see `Graphite::path`
+    template: Option<String>,
+
+    tag_order: Option<Vec<String>>,
+
+    /// Number of lines written between flushes
+    batch_size: Option<usize>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT.to_string());
+        let timeout =
+            humantime::parse_duration(&timeout).map_err(|e| Error::Duration(e, timeout))?;
+
+        Ok(Box::new(Graphite {
+            writer: LineWriter::new(self.address, self.tls.unwrap_or(false), timeout),
+
+            format: self.format.unwrap_or_default(),
+
+            prefix: self.prefix,
+
+            template: self.template.unwrap_or(DEFAULT_TEMPLATE.to_string()),
+
+            tag_order: self.tag_order.unwrap_or_default(),
+
+            batch_size: super::batch_size(self.batch_size, DEFAULT_BATCH_SIZE)?,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("graphite")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Format, Graphite, DEFAULT_TEMPLATE};
+    use crate::{
+        point::{Point, Value},
+        sink::tcp::LineWriter,
+    };
+    use chrono::{TimeZone, Utc};
+    use std::time::Duration;
+
+    fn graphite(format: Format, template: &str) -> Graphite {
+        Graphite {
+            writer: LineWriter::new("localhost:2003".to_string(), false, Duration::from_secs(1)),
+            format,
+            prefix: Some("photon".to_string()),
+            template: template.to_string(),
+            tag_order: vec!["source".to_string()],
+            batch_size: 1,
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(30000))
+            .field("alert", Value::Boolean(true))
+            .field("level", Value::String("green".to_string()))
+            .timestamp(Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap())
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "rte.eco2mix".to_string());
+        point.tags.insert("area".to_string(), "FR".to_string());
+
+        let now = Utc::now();
+        assert_eq!(
+            graphite(Format::Plaintext, DEFAULT_TEMPLATE).lines(&point, now),
+            vec![
+                "photon.eco2mix.rte_eco2mix.FR.alert 1 1664625600",
+                "photon.eco2mix.rte_eco2mix.FR.nuclear 30000 1664625600",
+            ]
+        );
+        assert_eq!(
+            graphite(
+                Format::Plaintext,
+                "{tags.area}.{tags.region}.{measurement}.{field}"
+            )
+            .lines(&point, now)[1],
+            "photon.FR.eco2mix.nuclear 30000 1664625600"
+        );
+        assert_eq!(
+            graphite(Format::Tagged, DEFAULT_TEMPLATE).lines(&point, now)[1],
+            "photon.eco2mix.nuclear;source=rte.eco2mix;area=FR 30000 1664625600"
+        );
+
+        point
+            .tags
+            .insert("area".to_string(), "FR\nIDF\t75".to_string());
+        assert_eq!(
+            graphite(Format::Plaintext, DEFAULT_TEMPLATE).lines(&point, now)[1],
+            "photon.eco2mix.rte_eco2mix.FR_IDF_75.nuclear 30000 1664625600"
+        );
+
+        point
+            .tags
+            .insert("area".to_string(), "~Île de;France".to_string());
+        assert_eq!(
+            graphite(Format::Tagged, DEFAULT_TEMPLATE).lines(&point, now)[1],
+            "photon.eco2mix.nuclear;source=rte.eco2mix;area=_Île_de_France 30000 1664625600"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    point::{Point, Points},
+    retry::{RetryConfig, RetryPolicy},
+    sink::Registration,
+};
+
+use super::{auth::Auth, codec, Sink, SinkConfig, SinkResult};
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to send request")]
+    Request(#[source] reqwest::Error),
+
+    #[error("failed to encode points")]
+    Encode(#[from] serde_json::Error),
+
+    #[error("request resulted in a non-success status code {0} with error: {1}")]
+    Status(StatusCode, String),
+
+    #[error("invalid header {0}")]
+    Header(String),
+
+    #[error("the template encoding requires a template")]
+    MissingTemplate,
+
+    #[error("missing {0} in a point of measurement {1}")]
+    MissingValue(String, String),
+}
+
+/// Encoding of the request bodies
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    /// JSON array of points
+    #[default]
+    Json,
+
+    /// One JSON point per line
+    Ndjson,
+
+    /// The template rendered for every point, one per line
+    Template,
+}
+
+/// Escaping of the values rendered in templates
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Escape {
+    #[default]
+    None,
+
+    /// Values are escaped as the content of JSON strings
+    Json,
+}
+
+impl Escape {
+    fn apply(&self, value: String) -> String {
+        match self {
+            Escape::None => value,
+            Escape::Json => {
+                let quoted = serde_json::Value::String(value).to_string();
+                quoted[1..quoted.len() - 1].to_string()
+            }
+        }
+    }
+}
+
+/// JSON document of a point, fields being written as plain JSON values
+fn document(point: &Point, now: DateTime<Utc>) -> serde_json::Value {
+    let fields = codec::json_fields(point);
+    let tags: BTreeMap<&String, &String> = point.tags.iter().collect();
+
+    json!({
+        "measurement": point.name,
+        "tags": tags,
+        "fields": fields,
+        "timestamp": point.timestamp.unwrap_or(now).to_rfc3339(),
+    })
+}
+
+/// Renders the template of a point, replacing `{measurement}`, `{timestamp}`, `{tags.<name>}`
+/// and `{fields.<name>}`. Missing tags and fields are an error and unknown placeholders are left
+/// untouched
+fn render(
+    template: &str,
+    point: &Point,
+    now: DateTime<Utc>,
+    escape: Escape,
+) -> Result<String, Error> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let placeholder = rest.find('}').map(|end| &rest[1..end]);
+        let value = placeholder.and_then(|p| match p.split_once('.') {
+            None if p == "measurement" => Some(Some(point.name.clone())),
+            None if p == "timestamp" => Some(Some(point.timestamp.unwrap_or(now).to_rfc3339())),
+            Some(("tags", name)) => Some(point.tags.get(name).cloned()),
+            Some(("fields", name)) => Some(point.fields.get(name).map(|v| v.to_string())),
+            _ => None,
+        });
+
+        match (placeholder, value) {
+            (Some(placeholder), Some(Some(value))) => {
+                out.push_str(&escape.apply(value));
+                rest = &rest[placeholder.len() + 2..];
+            }
+            (Some(placeholder), Some(None)) => {
+                return Err(Error::MissingValue(
+                    placeholder.to_string(),
+                    point.name.clone(),
+                ))
+            }
+            _ => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+struct Http {
+    url: Url,
+
+    encoding: Encoding,
+
+    template: Option<String>,
+
+    escape: Escape,
+
+    headers: HeaderMap,
+
+    auth: Auth,
+
+    batch_size: usize,
+
+    retry: RetryPolicy,
+}
+
+impl Http {
+    fn body(&self, points: &Points) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        let now = Utc::now();
+
+        match (self.encoding, &self.template) {
+            (Encoding::Json, _) => {
+                let documents: Vec<_> = points.iter().map(|p| document(p, now)).collect();
+                serde_json::to_writer(&mut body, &documents)?;
+            }
+            (Encoding::Ndjson, _) => {
+                for point in points.iter() {
+                    serde_json::to_writer(&mut body, &document(point, now))?;
+                    body.push(b'\n');
+                }
+            }
+            (Encoding::Template, Some(template)) => {
+                for point in points.iter() {
+                    body.extend(render(template, point, now, self.escape)?.into_bytes());
+                    body.push(b'\n');
+                }
+            }
+            (Encoding::Template, None) => return Err(Error::MissingTemplate),
+        }
+
+        Ok(body)
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self.encoding {
+            Encoding::Json => "application/json",
+            Encoding::Ndjson => "application/x-ndjson",
+            Encoding::Template => "text/plain; charset=utf-8",
+        }
+    }
+
+    fn post(&self, client: &reqwest::blocking::Client, points: &Points) -> SinkResult<()> {
+        let body = self.body(points)?;
+
+        debug!(
+            points = points.len(),
+            bytes = body.len(),
+            "sending points to {}",
+            self.url
+        );
+
+        let response = self
+            .retry
+            .send("http sink", || {
+                let request = client
+                    .post(self.url.clone())
+                    .header(CONTENT_TYPE, self.content_type())
+                    .headers(self.headers.clone())
+                    .body(body.clone());
+
+                self.auth.apply(request).send()
+            })
+            .map_err(Error::Request)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response
+                .text()
+                .unwrap_or("Failed to retrieve response text".to_string());
+            return Err(Error::Status(status, body).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for Http {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let client = reqwest::blocking::Client::new();
+
+        for batch in points.chunks(self.batch_size) {
+            self.post(&client, &batch)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    url: String,
+
+    encoding: Option<Encoding>,
+
+    /// Template of the `template` encoding, see `render`
+    template: Option<String>,
+
+    /// Escaping of the values rendered in the template, `json` for JSON templates
+    escape: Option<Escape>,
+
+    /// Headers added to every request, which may override the content type
+    headers: Option<BTreeMap<String, String>>,
+
+    username: Option<String>,
+
+    password: Option<String>,
+
+    bearer_token: Option<String>,
+
+    /// Maximum number of points per request
+    batch_size: Option<usize>,
+
+    retry: Option<RetryConfig>,
+}
+
+impl SinkConfig for Config {
+    const RETRY: bool = true;
+
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let encoding = self.encoding.unwrap_or_default();
+        if encoding == Encoding::Template && self.template.is_none() {
+            return Err(Error::MissingTemplate.into());
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.unwrap_or_default() {
+            let header = HeaderName::try_from(name.as_str())
+                .ok()
+                .zip(HeaderValue::try_from(value).ok())
+                .ok_or(Error::Header(name))?;
+            headers.insert(header.0, header.1);
+        }
+
+        let auth = Auth::new(
+            self.username,
+            self.password,
+            self.bearer_token.map(Auth::Bearer),
+            "bearer_token",
+        )?;
+
+        Ok(Box::new(Http {
+            url: self.url.parse()?,
+
+            encoding,
+
+            template: self.template,
+
+            escape: self.escape.unwrap_or_default(),
+
+            headers,
+
+            auth,
+
+            batch_size: super::batch_size(self.batch_size, DEFAULT_BATCH_SIZE)?,
+
+            retry: RetryPolicy::try_from(self.retry.unwrap_or_default())?,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("http")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Encoding, Escape, Http};
+    use crate::{
+        point::{Point, Value},
+        retry::RetryPolicy,
+        sink::auth::Auth,
+    };
+    use chrono::{TimeZone, Utc};
+    use reqwest::{header::HeaderMap, Url};
+
+    fn http(encoding: Encoding) -> Http {
+        Http {
+            url: Url::parse("http://localhost:8080").unwrap(),
+            encoding,
+            template: None,
+            escape: Escape::None,
+            headers: HeaderMap::new(),
+            auth: Auth::None,
+            batch_size: 1000,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    #[test]
+    fn test_body() {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(30000))
+            .timestamp(Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap())
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "eco2mix".to_string());
+        let points = vec![point.clone(), point].into();
+
+        let document = r#"{"fields":{"nuclear":30000},"measurement":"eco2mix","tags":{"source":"eco2mix"},"timestamp":"2022-10-01T12:00:00+00:00"}"#;
+        assert_eq!(
+            String::from_utf8(http(Encoding::Json).body(&points).unwrap()).unwrap(),
+            format!("[{document},{document}]")
+        );
+        assert_eq!(
+            String::from_utf8(http(Encoding::Ndjson).body(&points).unwrap()).unwrap(),
+            format!("{document}\n{document}\n")
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(30000))
+            .timestamp(Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap())
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "eco2mix".to_string());
+
+        assert_eq!(
+            render(
+                r#"{"name": "{measurement}", "source": "{tags.source}", "value": {fields.nuclear}, "at": "{timestamp}", "other": "{unknown}"}"#,
+                &point,
+                Utc::now(),
+                Escape::None
+            )
+            .unwrap(),
+            r#"{"name": "eco2mix", "source": "eco2mix", "value": 30000, "at": "2022-10-01T12:00:00+00:00", "other": "{unknown}"}"#
+        );
+
+        assert!(render("{fields.solar}", &point, Utc::now(), Escape::None).is_err());
+
+        point
+            .tags
+            .insert("region".to_string(), "Île-de-\"France\"\n".to_string());
+        assert_eq!(
+            render(
+                r#"{"region": "{tags.region}"}"#,
+                &point,
+                Utc::now(),
+                Escape::Json
+            )
+            .unwrap(),
+            r#"{"region": "Île-de-\"France\"\n"}"#
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{point::Points, sink::Registration};
+
+use super::{
+    influxdb::{self, Precision},
+    tcp::LineWriter,
+    Sink, SinkConfig, SinkResult,
+};
+
+const DEFAULT_BATCH_SIZE: usize = 5000;
+const DEFAULT_TIMEOUT: &str = "10s";
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("invalid timeout {1}: {0}")]
+    Duration(#[source] humantime::DurationError, String),
+}
+
+struct IlpTcp {
+    writer: LineWriter,
+
+    precision: Precision,
+
+    batch_size: usize,
+}
+
+impl Sink for IlpTcp {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let now = Utc::now();
+        let lines: Vec<String> = points
+            .iter()
+            .map(|p| influxdb::line(p, now, self.precision))
+            .collect::<Result<_, _>>()?;
+
+        self.writer.send(&lines, self.batch_size)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// `host:port` of the listener, e.g. `localhost:9009` for QuestDB
+    address: String,
+
+    tls: Option<bool>,
+
+    /// Timeout of the connection and of the writes
+    timeout: Option<String>,
+
+    precision: Option<Precision>,
+
+    /// Number of lines written between flushes
+    batch_size: Option<usize>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT.to_string());
+        let timeout =
+            humantime::parse_duration(&timeout).map_err(|e| Error::Duration(e, timeout))?;
+
+        Ok(Box::new(IlpTcp {
+            writer: LineWriter::new(self.address, self.tls.unwrap_or(false), timeout),
+
+            precision: self.precision.unwrap_or_default(),
+
+            batch_size: super::batch_size(self.batch_size, DEFAULT_BATCH_SIZE)?,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("ilp-tcp")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::{
+        point::{Point, Value},
+        sink::SinkConfig,
+    };
+    use chrono::{TimeZone, Utc};
+    use std::{io::Read, net::TcpListener};
+
+    #[test]
+    fn test_ilp_tcp_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config: Config = toml::from_str(&format!(
+            "address = \"{}\"\nprecision = \"s\"\nbatch_size = 1",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let sink = config.build().unwrap();
+
+        let points = (0..2)
+            .map(|hour| {
+                Point::builder("eco2mix")
+                    .field("nuclear", Value::Float(30000.5))
+                    .timestamp(Utc.with_ymd_and_hms(2022, 10, 1, hour, 0, 0).unwrap())
+                    .build()
+            })
+            .collect();
+        sink.sink(&points).unwrap();
+        drop(sink);
+
+        let mut received = String::new();
+        listener
+            .accept()
+            .unwrap()
+            .0
+            .read_to_string(&mut received)
+            .unwrap();
+        assert_eq!(
+            received,
+            "eco2mix nuclear=30000.5 1664582400\neco2mix nuclear=30000.5 1664586000\n"
+        );
+    }
+}
@@ -1,29 +1,149 @@
+use std::io::Write;
+
 use chrono::{DateTime, Utc};
-use reqwest::{StatusCode, Url};
-use rinfluxdb::line_protocol::{FieldValue, Line, LineBuilder};
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{
+    blocking::RequestBuilder,
+    header::{AUTHORIZATION, CONTENT_ENCODING},
+    StatusCode, Url,
+};
+use rinfluxdb::line_protocol::{FieldValue, LineBuilder};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::debug;
 
 use crate::{
     point::{Point, Points, Value},
+    retry::{RetryConfig, RetryPolicy},
     sink::Registration,
 };
 
 use super::{Sink, SinkConfig, SinkResult};
 
+const DEFAULT_BATCH_SIZE: usize = 5000;
+
 #[derive(Error, Debug)]
 enum Error {
     #[error("failed to send request")]
     Request(#[source] reqwest::Error),
 
-    #[error("write request resulted in a non-success status code {0} with error: {1}")]
-    Write(StatusCode, String),
+    #[error("{0} is required by the {1} write API")]
+    MissingOption(&'static str, &'static str),
+
+    #[error("failed to compress request body")]
+    Compress(#[source] std::io::Error),
+
+    #[error(
+        "write request resulted in a non-success status code {status} with error: {message}{}",
+        Error::details(code, line)
+    )]
+    Write {
+        status: StatusCode,
+
+        code: Option<String>,
+
+        message: String,
+
+        /// Line of the first rejected point, counted from the first point of the sink call
+        line: Option<usize>,
+    },
 }
 
-impl Into<FieldValue> for Value {
-    fn into(self) -> FieldValue {
+/// Timestamp of a point beyond the range of the nanosecond precision, 1677 to 2262
+#[derive(Error, Debug)]
+#[error("timestamp {0} of a {1} point does not fit in a nanosecond timestamp")]
+pub(super) struct TimestampError(DateTime<Utc>, String);
+
+/// Error body returned by the InfluxDB write API
+#[derive(Deserialize, Debug)]
+struct WriteError {
+    code: Option<String>,
+
+    /// InfluxDB 1.x reports the message as `error`
+    #[serde(alias = "error")]
+    message: String,
+
+    line: Option<usize>,
+}
+
+impl Error {
+    /// Code and line of a write error, when reported
+    fn details(code: &Option<String>, line: &Option<usize>) -> String {
+        match (code, line) {
+            (Some(code), Some(line)) => format!(" (code {code}, line {line})"),
+            (Some(code), None) => format!(" (code {code})"),
+            (None, Some(line)) => format!(" (line {line})"),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Builds a `Write` error from the response body, `offset` being the number of points sent
+    /// in previous batches
+    fn write(status: StatusCode, body: String, offset: usize) -> Self {
+        match serde_json::from_str::<WriteError>(&body) {
+            Ok(e) => Error::Write {
+                status,
+                code: e.code,
+                message: e.message,
+                line: e.line.map(|l| l + offset),
+            },
+            Err(_) => Error::Write {
+                status,
+                code: None,
+                message: body,
+                line: None,
+            },
+        }
+    }
+}
+
+/// Timestamp precision of the points written to InfluxDB
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Precision {
+    S,
+
+    Ms,
+
+    Us,
+
+    #[default]
+    Ns,
+}
+
+impl Precision {
+    fn as_str(&self) -> &'static str {
         match self {
+            Precision::S => "s",
+            Precision::Ms => "ms",
+            Precision::Us => "us",
+            Precision::Ns => "ns",
+        }
+    }
+
+    /// Precision as expected by the 1.x write API
+    fn as_v1_str(&self) -> &'static str {
+        match self {
+            Precision::S => "s",
+            Precision::Ms => "ms",
+            Precision::Us => "u",
+            Precision::Ns => "n",
+        }
+    }
+
+    fn timestamp(&self, timestamp: DateTime<Utc>) -> Option<i64> {
+        match self {
+            Precision::S => Some(timestamp.timestamp()),
+            Precision::Ms => Some(timestamp.timestamp_millis()),
+            Precision::Us => Some(timestamp.timestamp_micros()),
+            Precision::Ns => timestamp.timestamp_nanos_opt(),
+        }
+    }
+}
+
+impl From<Value> for FieldValue {
+    fn from(value: Value) -> Self {
+        match value {
             Value::Integer(i) => FieldValue::Integer(i),
             Value::Float(f) => FieldValue::Float(f),
             Value::Boolean(b) => FieldValue::Boolean(b),
@@ -32,7 +152,12 @@ impl Into<FieldValue> for Value {
     }
 }
 
-fn line(point: &Point, timestamp: DateTime<Utc>) -> Line {
+/// Serializes a point to the line protocol, `timestamp` being used for points without one
+pub(super) fn line(
+    point: &Point,
+    timestamp: DateTime<Utc>,
+    precision: Precision,
+) -> Result<String, TimestampError> {
     let mut builder = LineBuilder::new(point.name.clone());
 
     for (k, v) in &point.tags {
@@ -43,19 +168,137 @@ fn line(point: &Point, timestamp: DateTime<Utc>) -> Line {
         builder = builder.insert_field(k.clone(), v.clone());
     }
 
-    builder
-        .set_timestamp(point.timestamp.unwrap_or(timestamp))
-        .build()
+    // The line protocol crate always writes nanoseconds, the timestamp is appended manually to
+    // honor the precision
+    let timestamp = point.timestamp.unwrap_or(timestamp);
+    let timestamp = precision
+        .timestamp(timestamp)
+        .ok_or_else(|| TimestampError(timestamp, point.name.clone()))?;
+    Ok(format!("{} {}", builder.build(), timestamp))
+}
+
+/// Version of the InfluxDB write API, `v1` also covering VictoriaMetrics
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Version {
+    V1,
+
+    #[default]
+    V2,
+}
+
+enum Api {
+    V1 {
+        database: String,
+
+        retention_policy: Option<String>,
+
+        username: Option<String>,
+
+        password: Option<String>,
+    },
+
+    V2 {
+        token: String,
+
+        org: String,
+
+        bucket: String,
+    },
 }
 
 struct InfluxDB {
     host: Url,
 
-    token: String,
+    api: Api,
+
+    retry: RetryPolicy,
 
-    org: String,
+    batch_size: usize,
 
-    bucket: String,
+    gzip: bool,
+
+    precision: Precision,
+}
+
+impl InfluxDB {
+    fn request(&self, client: &reqwest::blocking::Client) -> RequestBuilder {
+        match &self.api {
+            Api::V1 {
+                database,
+                retention_policy,
+                username,
+                password,
+            } => {
+                let mut request = client
+                    .post(self.host.join("/write").expect("invalid URL"))
+                    .query(&[
+                        ("db", database.as_str()),
+                        ("precision", self.precision.as_v1_str()),
+                    ]);
+
+                if let Some(rp) = retention_policy {
+                    request = request.query(&[("rp", rp)]);
+                }
+
+                match username {
+                    Some(username) => request.basic_auth(username, password.as_ref()),
+                    None => request,
+                }
+            }
+            Api::V2 { token, org, bucket } => client
+                .post(self.host.join("/api/v2/write").expect("invalid URL"))
+                .header(AUTHORIZATION, format!("Token {token}"))
+                .query(&[
+                    ("org", org.as_str()),
+                    ("bucket", bucket.as_str()),
+                    ("precision", self.precision.as_str()),
+                ]),
+        }
+    }
+
+    fn write(
+        &self,
+        client: &reqwest::blocking::Client,
+        lines: &[String],
+        offset: usize,
+    ) -> SinkResult<()> {
+        let body = lines.join("\n").into_bytes();
+        let body = if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(&body)
+                .and_then(|_| encoder.finish())
+                .map_err(Error::Compress)?
+        } else {
+            body
+        };
+
+        debug!(points = lines.len(), bytes = body.len(), "writing batch");
+
+        let response = self
+            .retry
+            .send("influxdb write", || {
+                let mut request = self.request(client).body(body.clone());
+
+                if self.gzip {
+                    request = request.header(CONTENT_ENCODING, "gzip");
+                }
+
+                request.send()
+            })
+            .map_err(Error::Request)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response
+                .text()
+                .unwrap_or("Failed to retrieve response text".to_string());
+            return Err(Error::write(status, body, offset).into());
+        }
+
+        Ok(())
+    }
 }
 
 impl Sink for InfluxDB {
@@ -64,33 +307,14 @@ impl Sink for InfluxDB {
 
         let lines: Vec<String> = points
             .iter()
-            .map(|p| line(p, utc_now))
-            .map(|l| l.to_string())
-            .collect();
-
-        let body = lines.join("\n");
+            .map(|p| line(p, utc_now, self.precision))
+            .collect::<Result<_, _>>()?;
 
         debug!("sending {} points", lines.len());
 
         let client = reqwest::blocking::Client::new();
-        let write_url = self.host.join("/api/v2/write").expect("invalid URL");
-        let token_header_value = format!("Token {}", self.token);
-        let response = client
-            .post(write_url)
-            .header(reqwest::header::AUTHORIZATION, token_header_value)
-            .query(&[("org", &self.org), ("bucket", &self.bucket)])
-            .body(body)
-            .send()
-            .map_err(Error::Request)?;
-
-        if !response.status().is_success() {
-            return Err(Error::Write(
-                response.status(),
-                response
-                    .text()
-                    .unwrap_or("Failed to retrieve response text".to_string()),
-            )
-            .into());
+        for (i, batch) in lines.chunks(self.batch_size).enumerate() {
+            self.write(&client, batch, i * self.batch_size)?;
         }
 
         Ok(())
@@ -101,27 +325,214 @@ impl Sink for InfluxDB {
 struct Config {
     host: String,
 
-    token: String,
+    version: Option<Version>,
 
-    org: String,
+    token: Option<String>,
 
-    bucket: String,
+    org: Option<String>,
+
+    bucket: Option<String>,
+
+    database: Option<String>,
+
+    retention_policy: Option<String>,
+
+    username: Option<String>,
+
+    password: Option<String>,
+
+    retry: Option<RetryConfig>,
+
+    /// Maximum number of points per write request
+    batch_size: Option<usize>,
+
+    gzip: Option<bool>,
+
+    precision: Option<Precision>,
 }
 
-impl SinkConfig for Config {
-    fn build(self) -> SinkResult<Box<dyn Sink>> {
-        Ok(Box::new(InfluxDB {
-            host: self.host.parse()?,
+impl TryFrom<Config> for InfluxDB {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(config: Config) -> Result<Self, Self::Error> {
+        let api = match config.version.unwrap_or_default() {
+            Version::V1 => Api::V1 {
+                database: config
+                    .database
+                    .ok_or(Error::MissingOption("database", "v1"))?,
+                retention_policy: config.retention_policy,
+                username: config.username,
+                password: config.password,
+            },
+            Version::V2 => Api::V2 {
+                token: config.token.ok_or(Error::MissingOption("token", "v2"))?,
+                org: config.org.ok_or(Error::MissingOption("org", "v2"))?,
+                bucket: config.bucket.ok_or(Error::MissingOption("bucket", "v2"))?,
+            },
+        };
+
+        Ok(InfluxDB {
+            host: config.host.parse()?,
+
+            api,
 
-            token: self.token,
+            retry: RetryPolicy::try_from(config.retry.unwrap_or_default())?,
 
-            org: self.org,
+            batch_size: super::batch_size(config.batch_size, DEFAULT_BATCH_SIZE)?,
 
-            bucket: self.bucket,
-        }))
+            gzip: config.gzip.unwrap_or(false),
+
+            precision: config.precision.unwrap_or_default(),
+        })
+    }
+}
+
+impl SinkConfig for Config {
+    const RETRY: bool = true;
+
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        Ok(Box::new(InfluxDB::try_from(self)?))
     }
 }
 
 inventory::submit! {
     Registration::new::<Config>("influxdb")
 }
+
+#[cfg(test)]
+mod test {
+    use super::{line, Config, Error, InfluxDB, Precision};
+    use crate::{
+        point::{Point, Value},
+        value,
+    };
+    use chrono::{Duration, TimeZone, Utc};
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_line_precision() {
+        let timestamp =
+            Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap() + Duration::microseconds(123456);
+        let point = Point::builder("eco2mix")
+            .field("nuclear", value!(30000i64))
+            .build();
+
+        assert_eq!(
+            line(&point, timestamp, Precision::S).unwrap(),
+            "eco2mix nuclear=30000 1664625600"
+        );
+        assert_eq!(
+            line(&point, timestamp, Precision::Ms).unwrap(),
+            "eco2mix nuclear=30000 1664625600123"
+        );
+        assert_eq!(
+            line(&point, timestamp, Precision::Us).unwrap(),
+            "eco2mix nuclear=30000 1664625600123456"
+        );
+        assert_eq!(
+            line(&point, timestamp, Precision::Ns).unwrap(),
+            "eco2mix nuclear=30000 1664625600123456000"
+        );
+
+        let timestamp = Utc.with_ymd_and_hms(2300, 1, 1, 0, 0, 0).unwrap();
+        assert!(line(&point, timestamp, Precision::Us).is_ok());
+        assert!(line(&point, timestamp, Precision::Ns).is_err());
+    }
+
+    #[test]
+    fn test_write_error() {
+        let body = r#"{"code":"invalid","message":"partial write: field type conflict","line":2}"#;
+        let error = Error::write(StatusCode::BAD_REQUEST, body.to_string(), 5000);
+        assert_eq!(
+            error.to_string(),
+            "write request resulted in a non-success status code 400 Bad Request with error: \
+             partial write: field type conflict (code invalid, line 5002)"
+        );
+
+        match error {
+            Error::Write {
+                code,
+                message,
+                line,
+                ..
+            } => {
+                assert_eq!(code.as_deref(), Some("invalid"));
+                assert_eq!(message, "partial write: field type conflict");
+                assert_eq!(line, Some(5002));
+            }
+            e => panic!("unexpected error {e}"),
+        }
+
+        match Error::write(StatusCode::BAD_GATEWAY, "bad gateway".to_string(), 0) {
+            Error::Write { code, message, .. } => {
+                assert_eq!(code, None);
+                assert_eq!(message, "bad gateway");
+            }
+            e => panic!("unexpected error {e}"),
+        }
+    }
+
+    fn request(config: &str) -> reqwest::blocking::Request {
+        let config: Config = toml::from_str(config).unwrap();
+        let client = reqwest::blocking::Client::new();
+
+        InfluxDB::try_from(config)
+            .unwrap()
+            .request(&client)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_versions() {
+        let v2 = request(
+            r#"
+            host = "http://localhost:8086"
+            token = "secret"
+            org = "photon"
+            bucket = "eco2mix"
+            "#,
+        );
+        assert_eq!(
+            v2.url().as_str(),
+            "http://localhost:8086/api/v2/write?org=photon&bucket=eco2mix&precision=ns"
+        );
+        assert_eq!(v2.headers()["authorization"], "Token secret");
+
+        let v1 = request(
+            r#"
+            host = "http://localhost:8428"
+            version = "v1"
+            database = "photon"
+            retention_policy = "autogen"
+            username = "user"
+            password = "pass"
+            precision = "s"
+            "#,
+        );
+        assert_eq!(
+            v1.url().as_str(),
+            "http://localhost:8428/write?db=photon&precision=s&rp=autogen"
+        );
+        assert_eq!(v1.headers()["authorization"], "Basic dXNlcjpwYXNz");
+
+        let anonymous = request(
+            r#"
+            host = "http://localhost:8428"
+            version = "v1"
+            database = "photon"
+            "#,
+        );
+        assert!(!anonymous.headers().contains_key("authorization"));
+
+        let missing: Config = toml::from_str(
+            r#"
+            host = "http://localhost:8086"
+            version = "v2"
+            token = "secret"
+            "#,
+        )
+        .unwrap();
+        assert!(InfluxDB::try_from(missing).is_err());
+    }
+}
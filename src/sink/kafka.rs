@@ -0,0 +1,462 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rdkafka::{
+    config::ClientConfig,
+    error::{KafkaError, RDKafkaErrorCode},
+    producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext},
+    ClientContext,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    point::{Point, Points},
+    sink::Registration,
+};
+
+use super::{codec, Sink, SinkConfig, SinkResult};
+
+const DEFAULT_TIMEOUT: &str = "30s";
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to create the kafka producer: {0}")]
+    Create(#[source] KafkaError),
+
+    #[error("failed to produce to {1}: {0}")]
+    Produce(#[source] KafkaError, String),
+
+    #[error("failed to deliver {0} message(s), first error: {1}")]
+    Delivery(usize, KafkaError),
+
+    #[error("invalid timeout {1}: {0}")]
+    Duration(#[source] humantime::DurationError, String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Json,
+
+    /// Avro single object encoding, see `avro`
+    Avro,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+enum Compression {
+    #[default]
+    None,
+
+    Gzip,
+
+    Snappy,
+
+    Lz4,
+
+    Zstd,
+}
+
+impl Compression {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Snappy => "snappy",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// Escapes the separators of the message keys with a backslash
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+}
+
+/// Key of the messages, the measurement followed by the tags of the point sorted by name, so
+/// that every point of a series ends up in the same partition. Commas, equal signs and
+/// backslashes are escaped so that different tags never share a key
+fn key(point: &Point) -> String {
+    let tags: BTreeMap<&String, &String> = point.tags.iter().collect();
+
+    tags.into_iter()
+        .fold(escape(&point.name), |mut key, (k, v)| {
+            key.push_str(&format!(",{}={}", escape(k), escape(v)));
+            key
+        })
+}
+
+fn json(point: &Point, now: DateTime<Utc>) -> Vec<u8> {
+    let fields = codec::json_fields(point);
+    let tags: BTreeMap<&String, &String> = point.tags.iter().collect();
+
+    json!({
+        "name": point.name,
+        "timestamp": point.timestamp.unwrap_or(now).to_rfc3339(),
+        "tags": tags,
+        "fields": fields,
+    })
+    .to_string()
+    .into_bytes()
+}
+
+/// Avro encoding of the points, see the specification at
+/// https://avro.apache.org/docs/1.11.1/specification/. Points are `photon.Point` records made of
+/// a `name` string, a `timestamp` long with the `timestamp-micros` logical type, a `tags` map of
+/// strings and a `fields` map of `["long", "double", "boolean", "string"]` unions
+mod avro {
+    use std::collections::BTreeMap;
+
+    use chrono::{DateTime, Utc};
+
+    use crate::point::{Point, Value};
+
+    /// Parsing Canonical Form of the schema, from which its fingerprint is computed
+    const CANONICAL_SCHEMA: &str = r#"{"name":"photon.Point","type":"record","fields":[{"name":"name","type":"string"},{"name":"timestamp","type":"long"},{"name":"tags","type":{"type":"map","values":"string"}},{"name":"fields","type":{"type":"map","values":["long","double","boolean","string"]}}]}"#;
+
+    const EMPTY: u64 = 0xc15d213aa4d7a795;
+
+    const TABLE: [u64; 256] = {
+        let mut table = [0u64; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut fp = i as u64;
+            let mut bit = 0;
+            while bit < 8 {
+                fp = (fp >> 1) ^ (EMPTY & (fp & 1).wrapping_neg());
+                bit += 1;
+            }
+            table[i] = fp;
+            i += 1;
+        }
+        table
+    };
+
+    const SCHEMA_FINGERPRINT: u64 = fingerprint(CANONICAL_SCHEMA.as_bytes());
+
+    /// CRC-64-AVRO (Rabin) fingerprint
+    pub const fn fingerprint(data: &[u8]) -> u64 {
+        let mut fp = EMPTY;
+        let mut i = 0;
+        while i < data.len() {
+            fp = (fp >> 8) ^ TABLE[((fp ^ data[i] as u64) & 0xff) as usize];
+            i += 1;
+        }
+        fp
+    }
+
+    fn long(out: &mut Vec<u8>, value: i64) {
+        let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+        while zigzag >= 0x80 {
+            out.push((zigzag as u8 & 0x7f) | 0x80);
+            zigzag >>= 7;
+        }
+        out.push(zigzag as u8);
+    }
+
+    fn string(out: &mut Vec<u8>, value: &str) {
+        long(out, value.len() as i64);
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    fn map<'a, V: 'a>(
+        out: &mut Vec<u8>,
+        entries: impl ExactSizeIterator<Item = (&'a String, V)>,
+        mut value: impl FnMut(&mut Vec<u8>, V),
+    ) {
+        if entries.len() > 0 {
+            long(out, entries.len() as i64);
+            for (k, v) in entries {
+                string(out, k);
+                value(out, v);
+            }
+        }
+        long(out, 0);
+    }
+
+    /// Single object encoding of a point: a marker, the fingerprint of the schema and the
+    /// binary encoding of the record
+    pub fn encode(point: &Point, now: DateTime<Utc>) -> Vec<u8> {
+        let mut out = vec![0xc3, 0x01];
+        out.extend_from_slice(&SCHEMA_FINGERPRINT.to_le_bytes());
+
+        string(&mut out, &point.name);
+        long(&mut out, point.timestamp.unwrap_or(now).timestamp_micros());
+
+        let tags: BTreeMap<&String, &String> = point.tags.iter().collect();
+        map(&mut out, tags.into_iter(), |out, v| string(out, v));
+
+        let fields: BTreeMap<&String, &Value> = point.fields.iter().collect();
+        map(&mut out, fields.into_iter(), |out, v| match v {
+            Value::Integer(i) => {
+                long(out, 0);
+                long(out, *i);
+            }
+            Value::Float(f) => {
+                long(out, 1);
+                out.extend_from_slice(&f.to_le_bytes());
+            }
+            Value::Boolean(b) => {
+                long(out, 2);
+                out.push(*b as u8);
+            }
+            Value::String(s) => {
+                long(out, 3);
+                string(out, s);
+            }
+        });
+
+        out
+    }
+}
+
+/// Collects the delivery failures reported by the producer
+#[derive(Default)]
+struct Deliveries {
+    failures: Mutex<Vec<KafkaError>>,
+}
+
+impl ClientContext for Deliveries {}
+
+impl ProducerContext for Deliveries {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((e, _)) = result {
+            self.failures.lock().expect("poisoned lock").push(e.clone());
+        }
+    }
+}
+
+struct Kafka {
+    producer: BaseProducer<Deliveries>,
+
+    topic: String,
+
+    format: Format,
+
+    timeout: Duration,
+}
+
+impl Kafka {
+    /// Enqueues a message, waiting for the local queue to make room if it is full
+    fn send(&self, key: &str, payload: &[u8], deadline: Instant) -> Result<(), Error> {
+        loop {
+            let record = BaseRecord::to(&self.topic).key(key).payload(payload);
+            match self.producer.send(record) {
+                Ok(()) => return Ok(()),
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _))
+                    if Instant::now() < deadline =>
+                {
+                    self.producer.poll(Duration::from_millis(100));
+                }
+                Err((e, _)) => return Err(Error::Produce(e, self.topic.clone())),
+            }
+        }
+    }
+}
+
+impl Sink for Kafka {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let now = Utc::now();
+        let deadline = Instant::now() + self.timeout;
+
+        // Failures of messages of a previous call that timed out were already reported
+        self.producer
+            .context()
+            .failures
+            .lock()
+            .expect("poisoned lock")
+            .clear();
+
+        for point in points.iter() {
+            let payload = match self.format {
+                Format::Json => json(point, now),
+                Format::Avro => avro::encode(point, now),
+            };
+            self.send(&key(point), &payload, deadline)?;
+        }
+
+        debug!(
+            messages = points.len(),
+            topic = self.topic,
+            "flushing messages"
+        );
+        self.producer
+            .flush(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|e| Error::Produce(e, self.topic.clone()))?;
+
+        let mut failures = self
+            .producer
+            .context()
+            .failures
+            .lock()
+            .expect("poisoned lock");
+        let count = failures.len();
+        if let Some(first) = failures.drain(..).next() {
+            return Err(Error::Delivery(count, first).into());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// Comma-separated list of `host:port` bootstrap brokers
+    brokers: String,
+
+    topic: String,
+
+    format: Option<Format>,
+
+    /// Number of acknowledgements the leader must receive, `0`, `1` or `all`
+    acks: Option<String>,
+
+    compression: Option<Compression>,
+
+    /// Time to wait for the messages to be delivered
+    timeout: Option<String>,
+
+    /// Additional librdkafka properties, e.g. `security.protocol`
+    options: Option<BTreeMap<String, String>>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT.to_string());
+        let timeout =
+            humantime::parse_duration(&timeout).map_err(|e| Error::Duration(e, timeout))?;
+
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &self.brokers)
+            .set("acks", self.acks.as_deref().unwrap_or("all"))
+            .set(
+                "compression.codec",
+                self.compression.unwrap_or_default().as_str(),
+            )
+            .set("message.timeout.ms", timeout.as_millis().to_string());
+        for (k, v) in self.options.unwrap_or_default() {
+            config.set(k, v);
+        }
+
+        let producer = config
+            .create_with_context(Deliveries::default())
+            .map_err(Error::Create)?;
+
+        Ok(Box::new(Kafka {
+            producer,
+
+            topic: self.topic,
+
+            format: self.format.unwrap_or_default(),
+
+            timeout,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("kafka")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{avro, json, key, Config, Error};
+    use crate::{
+        point::{Point, Value},
+        sink::SinkConfig,
+    };
+    use chrono::{TimeZone, Utc};
+
+    fn point() -> Point {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(-1))
+            .field("status", Value::String("ok".to_string()))
+            .timestamp(Utc.timestamp_micros(1).unwrap())
+            .build();
+        point.tags.insert("source".to_string(), "rte".to_string());
+        point.tags.insert("region".to_string(), "FR".to_string());
+        point
+    }
+
+    #[test]
+    fn test_encoding() {
+        let point = point();
+        assert_eq!(key(&point), "eco2mix,region=FR,source=rte");
+
+        let mut escaped = Point::builder("eco,2mix").build();
+        escaped
+            .tags
+            .insert("region".to_string(), "FR,source=rte".to_string());
+        assert_eq!(key(&escaped), r"eco\,2mix,region=FR\,source\=rte");
+
+        assert_eq!(
+            String::from_utf8(json(&point, Utc::now())).unwrap(),
+            r#"{"fields":{"nuclear":-1,"status":"ok"},"name":"eco2mix","tags":{"region":"FR","source":"rte"},"timestamp":"1970-01-01T00:00:00.000001+00:00"}"#
+        );
+
+        // Reference value of the specification
+        assert_eq!(avro::fingerprint(br#""int""#), 0x7275d51a3f395c8f);
+
+        let encoded = avro::encode(&point, Utc::now());
+        assert_eq!(&encoded[..2], &[0xc3, 0x01]);
+        assert_eq!(
+            &encoded[10..],
+            &[
+                14, b'e', b'c', b'o', b'2', b'm', b'i', b'x', // name
+                2,    // timestamp
+                4, 12, b'r', b'e', b'g', b'i', b'o', b'n', 4, b'F', b'R', 12, b's', b'o', b'u',
+                b'r', b'c', b'e', 6, b'r', b't', b'e', 0, // tags
+                4, 14, b'n', b'u', b'c', b'l', b'e', b'a', b'r', 0, 1, 12, b's', b't', b'a', b't',
+                b'u', b's', 6, 4, b'o', b'k', 0, // fields
+            ][..]
+        );
+    }
+
+    #[test]
+    fn test_delivery_failure() {
+        // Nothing listens on the port, the message expires before being delivered
+        let config: Config = toml::from_str(
+            "brokers = \"127.0.0.1:1\"\ntopic = \"photon-test\"\ntimeout = \"10s\"\n\
+             options = { \"message.timeout.ms\" = \"100\" }",
+        )
+        .unwrap();
+
+        let error = config
+            .build()
+            .unwrap()
+            .sink(&vec![point()].into())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Delivery(1, _))
+        ));
+    }
+
+    /// Produces to the broker given by `PHOTON_KAFKA_BROKERS`, e.g. a local container started
+    /// with `docker run -p 9092:9092 apache/kafka`
+    #[test]
+    #[ignore]
+    fn test_kafka_sink() {
+        let brokers = std::env::var("PHOTON_KAFKA_BROKERS").unwrap();
+        let config: Config = toml::from_str(&format!(
+            "brokers = \"{brokers}\"\ntopic = \"photon-test\"\nformat = \"avro\"\ncompression = \"zstd\""
+        ))
+        .unwrap();
+
+        config.build().unwrap().sink(&vec![point()].into()).unwrap();
+    }
+}
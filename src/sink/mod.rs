@@ -2,10 +2,27 @@ use std::collections::HashMap;
 
 use thiserror::Error;
 
-use crate::point::Points;
+use crate::{point::Points, retry};
 
+mod auth;
+pub mod buffer;
+mod clickhouse;
+mod codec;
 mod console;
+mod elasticsearch;
+mod file;
+mod graphite;
+mod http;
+mod ilp_tcp;
 mod influxdb;
+mod kafka;
+mod mqtt;
+mod parquet;
+mod postgres;
+mod prometheus;
+mod remote_write;
+mod sqlite;
+mod tcp;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -26,13 +43,29 @@ pub trait Sink {
 }
 
 pub trait SinkConfig: Send + Sync {
+    /// Whether the configuration has a `retry` table, filled with the global `[retry]` settings
+    const RETRY: bool = false;
+
     fn build(self) -> SinkResult<Box<dyn Sink>>;
 }
 
+/// Validates the `batch_size` option of a sink, defaulting to `default`
+fn batch_size(batch_size: Option<usize>, default: usize) -> SinkResult<usize> {
+    match batch_size {
+        Some(0) => Err("batch_size must be greater than zero".into()),
+        Some(size) => Ok(size),
+        None => Ok(default),
+    }
+}
+
+type Builder = fn(&str, toml::Value) -> Result<Box<dyn Sink>, Error>;
+
 pub struct Registration {
     name: &'static str,
 
-    builder: fn(&str, toml::Value) -> Result<Box<dyn Sink>, Error>,
+    builder: Builder,
+
+    retry: bool,
 }
 
 impl Registration {
@@ -47,10 +80,18 @@ impl Registration {
                 .map_err(|e| Error::Config(e, name.to_string()))
         };
 
-        Self { name, builder }
+        Self {
+            name,
+            builder,
+            retry: SC::RETRY,
+        }
     }
 
-    pub fn build(name: &str, value: toml::Value) -> Result<Box<dyn Sink>, Error> {
+    pub fn build(
+        name: &str,
+        mut value: toml::Value,
+        global_retry: Option<&toml::Value>,
+    ) -> Result<Box<dyn Sink>, Error> {
         let registrations: HashMap<&'static str, &Registration> = inventory::iter::<Registration>()
             .map(|r| (r.name, r))
             .collect();
@@ -59,6 +100,10 @@ impl Registration {
             .get(name)
             .ok_or(Error::Unknown(name.to_string()))
             .and_then(|r| {
+                if r.retry {
+                    retry::merge(&mut value, global_retry);
+                }
+
                 let builder = r.builder;
 
                 builder(name, value)
@@ -0,0 +1,415 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rumqttc::{Client, ConnectionError, Event, Incoming, MqttOptions, Outgoing, QoS, Transport};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::{
+    point::{Point, Points, Value},
+    sink::Registration,
+};
+
+use super::{codec, Sink, SinkConfig, SinkResult};
+
+const DEFAULT_PORT: u16 = 1883;
+const DEFAULT_TLS_PORT: u16 = 8883;
+const DEFAULT_CLIENT_ID: &str = "photon";
+const DEFAULT_TOPIC: &str = "photon/{source}/{measurement}";
+const DEFAULT_TIMEOUT: &str = "30s";
+const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to publish to {1}: {0}")]
+    Publish(#[source] rumqttc::ClientError, String),
+
+    #[error("connection to the broker failed: {0}")]
+    Connection(#[from] Box<ConnectionError>),
+
+    #[error("timed out waiting for the broker to acknowledge {0} message(s)")]
+    Timeout(usize),
+
+    #[error("the connection to the broker was closed")]
+    Closed,
+
+    #[error("invalid qos {0}, expected 0, 1 or 2")]
+    Qos(u8),
+
+    #[error("invalid timeout {1}: {0}")]
+    Duration(#[source] humantime::DurationError, String),
+}
+
+/// Renders the topic template of a point, replacing `{source}` and `{measurement}`
+fn topic(template: &str, point: &Point) -> String {
+    let source = point
+        .tags
+        .get("source")
+        .map(String::as_str)
+        .unwrap_or("unknown");
+
+    template
+        .replace("{source}", source)
+        .replace("{measurement}", &point.name)
+}
+
+/// JSON payload of a point, fields being written as plain JSON values
+fn payload(point: &Point, now: DateTime<Utc>) -> serde_json::Value {
+    let fields = codec::json_fields(point);
+    let tags: BTreeMap<&String, &String> = point.tags.iter().collect();
+
+    json!({
+        "timestamp": point.timestamp.unwrap_or(now).to_rfc3339(),
+        "tags": tags,
+        "fields": fields,
+    })
+}
+
+/// Turns a name into an identifier made of lowercase letters, digits and underscores
+fn object_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Home Assistant discovery messages announcing a sensor for every field of a point, as
+/// `(topic, payload)` pairs. Boolean fields are announced as binary sensors
+fn discovery(prefix: &str, state_topic: &str, point: &Point) -> Vec<(String, serde_json::Value)> {
+    let source = point
+        .tags
+        .get("source")
+        .map(String::as_str)
+        .unwrap_or("unknown");
+
+    let mut fields: Vec<_> = point.fields.iter().collect();
+    fields.sort_by_key(|(name, _)| *name);
+
+    fields
+        .into_iter()
+        .map(|(field, value)| {
+            let id = object_id(&format!("photon_{source}_{}_{field}", point.name));
+            // Subscript access works with any field name, unlike attribute access
+            let key = field.replace('\'', "\\'");
+            let mut config = json!({
+                "name": format!("{} {field}", point.name),
+                "unique_id": id,
+                "object_id": id,
+                "state_topic": state_topic,
+                "device": {
+                    "identifiers": ["photon"],
+                    "name": "Photon",
+                },
+            });
+
+            let component = match value {
+                Value::Boolean(_) => {
+                    config["value_template"] = json!(format!(
+                        "{{{{ 'ON' if value_json.fields['{key}'] else 'OFF' }}}}"
+                    ));
+                    "binary_sensor"
+                }
+                Value::Integer(_) | Value::Float(_) => {
+                    config["value_template"] =
+                        json!(format!("{{{{ value_json.fields['{key}'] }}}}"));
+                    config["state_class"] = json!("measurement");
+                    "sensor"
+                }
+                Value::String(_) => {
+                    config["value_template"] =
+                        json!(format!("{{{{ value_json.fields['{key}'] }}}}"));
+                    "sensor"
+                }
+            };
+
+            (format!("{prefix}/{component}/{id}/config"), config)
+        })
+        .collect()
+}
+
+/// Drives the connection to the broker, forwarding its events to the sink. The connection
+/// is re-established on the next iteration after an error
+fn run(mut connection: rumqttc::Connection, events: Sender<Result<Event, ConnectionError>>) {
+    for event in connection.iter() {
+        let failed = event.is_err();
+        if events.send(event).is_err() {
+            return;
+        }
+
+        if failed {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+struct Mqtt {
+    client: Client,
+
+    events: Mutex<Receiver<Result<Event, ConnectionError>>>,
+
+    topic: String,
+
+    qos: QoS,
+
+    retain: bool,
+
+    timeout: Duration,
+
+    /// Prefix of the Home Assistant discovery topics, if enabled
+    discovery: Option<String>,
+
+    announced: Mutex<HashSet<String>>,
+}
+
+impl Mqtt {
+    fn publish(&self, topic: String, retain: bool, payload: Vec<u8>) -> Result<(), Error> {
+        self.client
+            .publish(topic.clone(), self.qos, retain, payload)
+            .map_err(|e| Error::Publish(e, topic))
+    }
+
+    /// Waits for the broker to acknowledge `count` messages, or for the messages to be sent
+    /// with QoS 0
+    fn wait(
+        &self,
+        events: &Receiver<Result<Event, ConnectionError>>,
+        count: usize,
+    ) -> Result<(), Error> {
+        let deadline = Instant::now() + self.timeout;
+        let mut pending = count;
+
+        while pending > 0 {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let event = match events.recv_timeout(remaining) {
+                Ok(event) => event.map_err(Box::new)?,
+                Err(RecvTimeoutError::Timeout) => return Err(Error::Timeout(pending)),
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::Closed),
+            };
+
+            let acknowledged = matches!(
+                (self.qos, event),
+                (QoS::AtMostOnce, Event::Outgoing(Outgoing::Publish(_)))
+                    | (QoS::AtLeastOnce, Event::Incoming(Incoming::PubAck(_)))
+                    | (QoS::ExactlyOnce, Event::Incoming(Incoming::PubComp(_)))
+            );
+            if acknowledged {
+                pending -= 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for Mqtt {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let events = self.events.lock().expect("poisoned lock");
+
+        // Events left over by a previous call, e.g. after a timeout
+        while let Ok(event) = events.try_recv() {
+            if let Err(e) = event {
+                warn!("mqtt connection error: {e}");
+            }
+        }
+
+        let now = Utc::now();
+        let mut count = 0;
+
+        for point in points.iter() {
+            let topic = topic(&self.topic, point);
+
+            if let Some(prefix) = &self.discovery {
+                let mut announced = self.announced.lock().expect("poisoned lock");
+                for (config_topic, config) in discovery(prefix, &topic, point) {
+                    if announced.insert(config_topic.clone()) {
+                        debug!(topic = config_topic, "announcing home assistant sensor");
+                        self.publish(config_topic, true, config.to_string().into_bytes())?;
+                        count += 1;
+                    }
+                }
+            }
+
+            self.publish(
+                topic,
+                self.retain,
+                payload(point, now).to_string().into_bytes(),
+            )?;
+            count += 1;
+        }
+
+        debug!(messages = count, "waiting for the broker");
+        if let Err(e) = self.wait(&events, count) {
+            // Announce again once the broker is reachable
+            self.announced.lock().expect("poisoned lock").clear();
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    host: String,
+
+    port: Option<u16>,
+
+    client_id: Option<String>,
+
+    username: Option<String>,
+
+    password: Option<String>,
+
+    /// Connect with TLS, using the system root certificates
+    tls: Option<bool>,
+
+    /// Topic template, see `topic`
+    topic: Option<String>,
+
+    qos: Option<u8>,
+
+    retain: Option<bool>,
+
+    /// Time to wait for the broker to acknowledge the messages
+    timeout: Option<String>,
+
+    /// Publish Home Assistant MQTT discovery messages
+    discovery: Option<bool>,
+
+    discovery_prefix: Option<String>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let tls = self.tls.unwrap_or(false);
+        let port = self
+            .port
+            .unwrap_or(if tls { DEFAULT_TLS_PORT } else { DEFAULT_PORT });
+
+        let qos = match self.qos.unwrap_or(0) {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            qos => return Err(Error::Qos(qos).into()),
+        };
+
+        let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT.to_string());
+        let timeout =
+            humantime::parse_duration(&timeout).map_err(|e| Error::Duration(e, timeout))?;
+
+        let client_id = self.client_id.unwrap_or(DEFAULT_CLIENT_ID.to_string());
+        let mut options = MqttOptions::new(client_id, self.host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = self.username {
+            options.set_credentials(username, self.password.unwrap_or_default());
+        }
+        if tls {
+            options.set_transport(Transport::tls_with_default_config());
+        }
+
+        let (client, connection) = Client::new(options, 64);
+        let (sender, events) = mpsc::channel();
+        std::thread::spawn(move || run(connection, sender));
+
+        Ok(Box::new(Mqtt {
+            client,
+
+            events: Mutex::new(events),
+
+            topic: self.topic.unwrap_or(DEFAULT_TOPIC.to_string()),
+
+            qos,
+
+            retain: self.retain.unwrap_or(false),
+
+            timeout,
+
+            discovery: self.discovery.unwrap_or(false).then(|| {
+                self.discovery_prefix
+                    .unwrap_or(DEFAULT_DISCOVERY_PREFIX.to_string())
+            }),
+
+            announced: Mutex::new(HashSet::new()),
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("mqtt")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{discovery, payload, topic};
+    use crate::point::{Point, Value};
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    fn point() -> Point {
+        let mut point = Point::builder("signals")
+            .field("level", Value::Integer(2))
+            .field("alert", Value::Boolean(true))
+            .timestamp(Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap())
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "ecowatt".to_string());
+        point
+    }
+
+    #[test]
+    fn test_payload() {
+        let point = point();
+
+        assert_eq!(
+            topic("photon/{source}/{measurement}", &point),
+            "photon/ecowatt/signals"
+        );
+        assert_eq!(
+            payload(&point, Utc::now()),
+            json!({
+                "timestamp": "2022-10-01T12:00:00+00:00",
+                "tags": {"source": "ecowatt"},
+                "fields": {"alert": true, "level": 2},
+            })
+        );
+    }
+
+    #[test]
+    fn test_discovery() {
+        let messages = discovery("homeassistant", "photon/ecowatt/signals", &point());
+        let topics: Vec<_> = messages.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(
+            topics,
+            vec![
+                "homeassistant/binary_sensor/photon_ecowatt_signals_alert/config",
+                "homeassistant/sensor/photon_ecowatt_signals_level/config"
+            ]
+        );
+
+        let (_, level) = &messages[1];
+        assert_eq!(level["state_topic"], "photon/ecowatt/signals");
+        assert_eq!(level["value_template"], "{{ value_json.fields['level'] }}");
+        assert_eq!(level["device"]["identifiers"], json!(["photon"]));
+
+        let (_, alert) = &messages[0];
+        assert_eq!(
+            alert["value_template"],
+            "{{ 'ON' if value_json.fields['alert'] else 'OFF' }}"
+        );
+    }
+}
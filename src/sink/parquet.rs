@@ -0,0 +1,378 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampNanosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{DateTime, Utc};
+use parquet::{arrow::ArrowWriter, basic, file::properties::WriterProperties};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, field};
+
+use crate::{
+    point::{Point, Points, Value},
+    sink::Registration,
+};
+
+use super::{Sink, SinkConfig, SinkResult};
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("error writing {1}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+
+    #[error("error writing {1}: {0}")]
+    Parquet(#[source] parquet::errors::ParquetError, PathBuf),
+
+    #[error("invalid record batch: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+
+    #[error("column {0} is both a tag and a field")]
+    DuplicateColumn(String),
+
+    #[error("timestamp {0} of a {1} point does not fit in a nanosecond timestamp")]
+    Timestamp(DateTime<Utc>, String),
+}
+
+/// Escapes a partition value the way Hive does, so that measurement names cannot add
+/// directories to the partition path
+fn escape(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '"' | '#' | '%' | '\'' | '*' | '/' | ':' | '=' | '?' | '\\' | '{' | '[' | ']' | '^' => {
+                format!("%{:02X}", c as u32)
+            }
+            c if c.is_ascii_control() => format!("%{:02X}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+enum Compression {
+    None,
+
+    #[default]
+    Snappy,
+
+    Gzip,
+
+    Zstd,
+}
+
+impl From<Compression> for basic::Compression {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => basic::Compression::UNCOMPRESSED,
+            Compression::Snappy => basic::Compression::SNAPPY,
+            Compression::Gzip => basic::Compression::GZIP(Default::default()),
+            Compression::Zstd => basic::Compression::ZSTD(Default::default()),
+        }
+    }
+}
+
+/// Arrow type of a field column, fields holding both integers and floats being widened to
+/// floats and any other mix of types being written as strings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColumnType {
+    Integer,
+
+    Float,
+
+    Boolean,
+
+    String,
+}
+
+impl ColumnType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Integer(_) => ColumnType::Integer,
+            Value::Float(_) => ColumnType::Float,
+            Value::Boolean(_) => ColumnType::Boolean,
+            Value::String(_) => ColumnType::String,
+        }
+    }
+
+    fn merge(self, other: ColumnType) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => {
+                ColumnType::Float
+            }
+            _ => ColumnType::String,
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            ColumnType::Integer => DataType::Int64,
+            ColumnType::Float => DataType::Float64,
+            ColumnType::Boolean => DataType::Boolean,
+            ColumnType::String => DataType::Utf8,
+        }
+    }
+
+    fn array<'a>(&self, values: impl Iterator<Item = Option<&'a Value>>) -> ArrayRef {
+        match self {
+            ColumnType::Integer => Arc::new(Int64Array::from_iter(values.map(|v| match v {
+                Some(Value::Integer(i)) => Some(*i),
+                _ => None,
+            }))),
+            ColumnType::Float => Arc::new(Float64Array::from_iter(values.map(|v| match v {
+                Some(Value::Integer(i)) => Some(*i as f64),
+                Some(Value::Float(f)) => Some(*f),
+                _ => None,
+            }))),
+            ColumnType::Boolean => Arc::new(BooleanArray::from_iter(values.map(|v| match v {
+                Some(Value::Boolean(b)) => Some(*b),
+                _ => None,
+            }))),
+            ColumnType::String => Arc::new(StringArray::from_iter(
+                values.map(|v| v.map(|v| v.to_string())),
+            )),
+        }
+    }
+}
+
+/// Builds a record batch of the points of a partition, with a `timestamp` column followed by
+/// the tags and the fields of the points sorted by name
+fn record_batch(points: &[&Point], now: DateTime<Utc>) -> Result<RecordBatch, Error> {
+    let tags: BTreeSet<&String> = points.iter().flat_map(|p| p.tags.keys()).collect();
+
+    let mut fields: BTreeMap<&String, ColumnType> = BTreeMap::new();
+    for (name, value) in points.iter().flat_map(|p| &p.fields) {
+        if tags.contains(name) {
+            return Err(Error::DuplicateColumn(name.clone()));
+        }
+
+        let column_type = ColumnType::of(value);
+        fields
+            .entry(name)
+            .and_modify(|t| *t = t.merge(column_type))
+            .or_insert(column_type);
+    }
+
+    let mut schema = vec![Field::new(
+        "timestamp",
+        DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        false,
+    )];
+    let timestamps = points
+        .iter()
+        .map(|p| {
+            let timestamp = p.timestamp.unwrap_or(now);
+            timestamp
+                .timestamp_nanos_opt()
+                .ok_or_else(|| Error::Timestamp(timestamp, p.name.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(
+        TimestampNanosecondArray::from(timestamps).with_timezone("UTC"),
+    )];
+
+    for tag in tags {
+        schema.push(Field::new(tag, DataType::Utf8, true));
+        columns.push(Arc::new(StringArray::from_iter(
+            points.iter().map(|p| p.tags.get(tag)),
+        )));
+    }
+
+    for (name, column_type) in fields {
+        schema.push(Field::new(name, column_type.data_type(), true));
+        columns.push(column_type.array(points.iter().map(|p| p.fields.get(name))));
+    }
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(schema)),
+        columns,
+    )?)
+}
+
+struct Parquet {
+    path: PathBuf,
+
+    compression: Compression,
+
+    sequence: AtomicU64,
+}
+
+impl Parquet {
+    fn write(&self, dir: PathBuf, batch: RecordBatch) -> Result<(), Error> {
+        fs::create_dir_all(&dir).map_err(|e| Error::Io(e, dir.clone()))?;
+
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let path = dir.join(format!("part-{timestamp:020}-{sequence:06}.parquet"));
+
+        debug!(
+            path = field::display(path.display()),
+            rows = batch.num_rows(),
+            "writing parquet file"
+        );
+
+        let file = File::create(&path).map_err(|e| Error::Io(e, path.clone()))?;
+        let properties = WriterProperties::builder()
+            .set_compression(self.compression.into())
+            .build();
+
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(properties))
+            .map_err(|e| Error::Parquet(e, path.clone()))?;
+        writer
+            .write(&batch)
+            .and_then(|_| writer.close().map(|_| ()))
+            .map_err(|e| Error::Parquet(e, path.clone()))
+    }
+}
+
+impl Sink for Parquet {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let now = Utc::now();
+
+        // Hive-style partitions, understood by most analytical tools
+        let mut partitions: BTreeMap<PathBuf, Vec<&Point>> = BTreeMap::new();
+        for point in points.iter() {
+            let date = point.timestamp.unwrap_or(now).format("%Y-%m-%d");
+            let dir = self
+                .path
+                .join(format!("measurement={}", escape(&point.name)))
+                .join(format!("date={date}"));
+
+            partitions.entry(dir).or_default().push(point);
+        }
+
+        for (dir, points) in partitions {
+            let batch = record_batch(&points, now)?;
+            self.write(dir, batch)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// Root directory of the partitions
+    path: String,
+
+    compression: Option<Compression>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        Ok(Box::new(Parquet {
+            path: PathBuf::from(self.path),
+
+            compression: self.compression.unwrap_or_default(),
+
+            sequence: AtomicU64::new(0),
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("parquet")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, record_batch, Config};
+    use crate::{
+        point::{Point, Value},
+        sink::SinkConfig,
+        value,
+    };
+    use arrow_schema::DataType;
+    use chrono::{TimeZone, Utc};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_parquet_sink() {
+        let dir = std::env::temp_dir().join(format!("photon-parquet-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let config: Config = toml::from_str(&format!(
+            "path = \"{}\"\ncompression = \"zstd\"",
+            dir.display()
+        ))
+        .unwrap();
+        let sink = config.build().unwrap();
+
+        let timestamp = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+        let mut nuclear = Point::builder("eco2mix")
+            .field("value", value!(30000i64))
+            .field("unit", Value::String("MW".to_string()))
+            .timestamp(timestamp)
+            .build();
+        nuclear
+            .tags
+            .insert("source".to_string(), "eco2mix".to_string());
+        let solar = Point::builder("eco2mix")
+            .field("value", value!(1500.5))
+            .timestamp(timestamp)
+            .build();
+        let next_day = Point::builder("eco2mix")
+            .field("value", Value::Boolean(true))
+            .timestamp(timestamp + chrono::Duration::days(1))
+            .build();
+
+        sink.sink(&vec![nuclear, solar, next_day].into()).unwrap();
+
+        let partition = dir.join("measurement=eco2mix/date=2022-10-01");
+        let files: Vec<_> = std::fs::read_dir(&partition)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(dir.join("measurement=eco2mix/date=2022-10-02").exists());
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&files[0]).unwrap())
+                .unwrap()
+                .build()
+                .unwrap();
+        let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+        let schema = batches[0].schema();
+
+        assert_eq!(batches[0].num_rows(), 2);
+        let columns: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|f| (f.name().as_str(), f.data_type().clone()))
+            .collect();
+        assert_eq!(
+            &columns[1..],
+            &[
+                ("source", DataType::Utf8),
+                ("unit", DataType::Utf8),
+                ("value", DataType::Float64),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_partitions() {
+        assert_eq!(escape("eco2mix"), "eco2mix");
+        assert_eq!(escape("../eco2mix=1"), "..%2Feco2mix%3D1");
+
+        let point = Point::builder("eco2mix")
+            .field("value", value!(1))
+            .timestamp(Utc.with_ymd_and_hms(2300, 1, 1, 0, 0, 0).unwrap())
+            .build();
+        assert!(record_batch(&[&point], Utc::now()).is_err());
+    }
+}
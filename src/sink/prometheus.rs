@@ -0,0 +1,391 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tiny_http::{Header, Method, Response, Server};
+use tracing::{debug, info, warn};
+
+use crate::{
+    point::{Point, Points, Value},
+    sink::Registration,
+};
+
+use super::{Sink, SinkConfig, SinkResult};
+
+const DEFAULT_ADDRESS: &str = "0.0.0.0:9184";
+const DEFAULT_PATH: &str = "/metrics";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to listen on {1}: {0}")]
+    Listen(#[source] Box<dyn std::error::Error + Send + Sync>, String),
+
+    #[error("invalid staleness {1}: {0}")]
+    Staleness(#[source] humantime::DurationError, String),
+}
+
+/// Tag of a point whose label name is already taken by another tag or a reserved label
+#[derive(Error, Debug)]
+#[error("tag {tag} of measurement {measurement} maps to the label {label} which is already used")]
+pub(super) struct LabelError {
+    tag: String,
+
+    label: String,
+
+    measurement: String,
+}
+
+/// How fields holding strings are exported
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Strings {
+    #[default]
+    Skip,
+
+    /// As an info-style `<measurement>_<field>_info{value="..."} 1` metric
+    Labels,
+}
+
+/// Replaces the characters that are not allowed in metric and label names
+pub(super) fn sanitize(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) || sanitized.is_empty() {
+        sanitized.insert(0, '_');
+    }
+
+    sanitized
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Formats a sample value, with the spelling of non-finite values expected by Prometheus
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+/// Sorted labels of the tags of a point, failing when two tags or a tag and one of the `reserved`
+/// label names map to the same name
+pub(super) fn tag_labels(point: &Point, reserved: &[&str]) -> Result<Labels, LabelError> {
+    let mut labels = Labels::with_capacity(point.tags.len());
+
+    for (tag, value) in &point.tags {
+        let label = sanitize(tag);
+        if reserved.contains(&label.as_str()) || labels.iter().any(|(l, _)| *l == label) {
+            return Err(LabelError {
+                tag: tag.clone(),
+                label,
+                measurement: point.name.clone(),
+            });
+        }
+
+        labels.push((label, value.clone()));
+    }
+
+    labels.sort();
+    Ok(labels)
+}
+
+struct Sample {
+    value: f64,
+
+    /// Value of the `value` label of info-style metrics
+    info: Option<String>,
+
+    /// Timestamp of the point, the most recent point of a series being exported
+    timestamp: Option<DateTime<Utc>>,
+
+    updated_at: Instant,
+}
+
+/// Latest sample of every series, by metric name and labels
+#[derive(Default)]
+struct Registry {
+    metrics: BTreeMap<String, BTreeMap<Labels, Sample>>,
+}
+
+impl Registry {
+    /// Updates the series of the points, the points being checked first so that the registry is
+    /// left unchanged when one of them is rejected
+    fn update(
+        &mut self,
+        points: &Points,
+        strings: Strings,
+        now: Instant,
+    ) -> Result<(), LabelError> {
+        let labels = points
+            .iter()
+            .map(|point| {
+                let info = strings == Strings::Labels
+                    && point.fields.values().any(|v| matches!(v, Value::String(_)));
+                let reserved: &[&str] = if info {
+                    &["__name__", "value"]
+                } else {
+                    &["__name__"]
+                };
+
+                tag_labels(point, reserved)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (point, labels) in points.iter().zip(labels) {
+            for (field, value) in &point.fields {
+                let name = sanitize(&format!("{}_{}", point.name, field));
+
+                let (name, value, info) = match value {
+                    Value::Integer(i) => (name, *i as f64, None),
+                    Value::Float(f) => (name, *f, None),
+                    Value::Boolean(b) => (name, if *b { 1.0 } else { 0.0 }, None),
+                    Value::String(s) if strings == Strings::Labels => {
+                        (format!("{name}_info"), 1.0, Some(s.clone()))
+                    }
+                    Value::String(_) => continue,
+                };
+
+                let series = self.metrics.entry(name).or_default();
+                let outdated = series
+                    .get(&labels)
+                    .is_some_and(|s| s.timestamp > point.timestamp);
+
+                if !outdated {
+                    series.insert(
+                        labels.clone(),
+                        Sample {
+                            value,
+                            info,
+                            timestamp: point.timestamp,
+                            updated_at: now,
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the series that were not updated for longer than `staleness`
+    fn expire(&mut self, staleness: Duration, now: Instant) {
+        for series in self.metrics.values_mut() {
+            series.retain(|_, sample| now.duration_since(sample.updated_at) < staleness);
+        }
+
+        self.metrics.retain(|_, series| !series.is_empty());
+    }
+
+    /// Renders the series in the Prometheus text exposition format
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        for (name, series) in &self.metrics {
+            let _ = writeln!(out, "# TYPE {name} gauge");
+
+            for (labels, sample) in series {
+                let labels = labels
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v))
+                    .chain(sample.info.iter().map(|v| ("value", v)))
+                    .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                let value = format_value(sample.value);
+                if labels.is_empty() {
+                    let _ = writeln!(out, "{name} {value}");
+                } else {
+                    let _ = writeln!(out, "{name}{{{labels}}} {value}");
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// Serves the registry until the process exits
+fn serve(
+    server: Server,
+    path: String,
+    registry: Arc<Mutex<Registry>>,
+    staleness: Option<Duration>,
+) {
+    for request in server.incoming_requests() {
+        let response =
+            if request.method() == &Method::Get && request.url().split('?').next() == Some(&path) {
+                let mut registry = registry.lock().expect("poisoned lock");
+                if let Some(staleness) = staleness {
+                    registry.expire(staleness, Instant::now());
+                }
+
+                let content_type =
+                    Header::from_bytes("Content-Type", CONTENT_TYPE).expect("invalid header");
+                Response::from_string(registry.render()).with_header(content_type)
+            } else {
+                Response::from_string("not found").with_status_code(404)
+            };
+
+        if let Err(e) = request.respond(response) {
+            warn!("failed to respond to a scrape: {e}");
+        }
+    }
+}
+
+struct Prometheus {
+    strings: Strings,
+
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Sink for Prometheus {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        debug!(points = points.len(), "updating metrics");
+
+        let mut registry = self.registry.lock().expect("poisoned lock");
+        registry.update(points, self.strings, Instant::now())?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// Address the HTTP server listens on
+    address: Option<String>,
+
+    path: Option<String>,
+
+    strings: Option<Strings>,
+
+    /// Time after which series that were not updated disappear
+    staleness: Option<String>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let address = self.address.unwrap_or(DEFAULT_ADDRESS.to_string());
+        let path = self.path.unwrap_or(DEFAULT_PATH.to_string());
+        let staleness = self
+            .staleness
+            .map(|s| humantime::parse_duration(&s).map_err(|e| Error::Staleness(e, s)))
+            .transpose()?;
+
+        let server = Server::http(&address).map_err(|e| Error::Listen(e, address.clone()))?;
+        info!(address, path, "serving prometheus metrics");
+
+        let registry = Arc::new(Mutex::new(Registry::default()));
+        let served = registry.clone();
+        std::thread::spawn(move || serve(server, path, served, staleness));
+
+        Ok(Box::new(Prometheus {
+            strings: self.strings.unwrap_or_default(),
+
+            registry,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("prometheus")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_value, Registry, Strings};
+    use crate::point::{Point, Points, Value};
+    use chrono::{TimeZone, Utc};
+    use std::time::{Duration, Instant};
+
+    fn points(hour: u32, nuclear: i64) -> Points {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(nuclear))
+            .field("level", Value::String("green \"ok\"".to_string()))
+            .timestamp(Utc.with_ymd_and_hms(2022, 10, 1, hour, 0, 0).unwrap())
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "eco-2mix".to_string());
+
+        vec![point].into()
+    }
+
+    #[test]
+    fn test_registry() {
+        let now = Instant::now();
+        let mut registry = Registry::default();
+
+        registry
+            .update(&points(13, 31000), Strings::Skip, now)
+            .unwrap();
+        registry
+            .update(&points(12, 30000), Strings::Skip, now)
+            .unwrap();
+        assert_eq!(
+            registry.render(),
+            "# TYPE eco2mix_nuclear gauge\neco2mix_nuclear{source=\"eco-2mix\"} 31000\n"
+        );
+
+        registry
+            .update(&points(14, 32000), Strings::Labels, now)
+            .unwrap();
+        assert_eq!(
+            registry.render(),
+            "# TYPE eco2mix_level_info gauge\n\
+             eco2mix_level_info{source=\"eco-2mix\",value=\"green \\\"ok\\\"\"} 1\n\
+             # TYPE eco2mix_nuclear gauge\neco2mix_nuclear{source=\"eco-2mix\"} 32000\n"
+        );
+
+        registry.expire(Duration::from_secs(60), now + Duration::from_secs(30));
+        assert_eq!(registry.metrics.len(), 2);
+        registry.expire(Duration::from_secs(60), now + Duration::from_secs(90));
+        assert_eq!(registry.render(), "");
+    }
+
+    #[test]
+    fn test_label_collisions() {
+        let now = Instant::now();
+        let mut registry = Registry::default();
+
+        let mut colliding = points(12, 30000);
+        colliding.tag_all("source_name", "eco2mix");
+        colliding.tag_all("source-name", "eco2mix");
+        assert!(registry.update(&colliding, Strings::Skip, now).is_err());
+
+        let mut value = points(12, 30000);
+        value.tag_all("value", "eco2mix");
+        assert!(registry.update(&value, Strings::Labels, now).is_err());
+        assert_eq!(registry.render(), "");
+
+        registry.update(&value, Strings::Skip, now).unwrap();
+        assert_eq!(registry.metrics.len(), 1);
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(1.5), "1.5");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(f64::NAN), "NaN");
+    }
+}
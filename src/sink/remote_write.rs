@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use prost::Message;
+use reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    point::{Points, Value},
+    retry::{RetryConfig, RetryPolicy},
+    sink::Registration,
+};
+
+use super::{
+    auth::Auth,
+    prometheus::{sanitize, tag_labels, LabelError},
+    Sink, SinkConfig, SinkResult,
+};
+
+const DEFAULT_BATCH_SIZE: usize = 5000;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to send request")]
+    Request(#[source] reqwest::Error),
+
+    #[error("failed to compress request body")]
+    Compress(#[source] snap::Error),
+
+    #[error("remote write resulted in a non-success status code {0} with error: {1}")]
+    Write(StatusCode, String),
+}
+
+/// Messages of the Prometheus remote write protocol, see `prompb/remote.proto` and
+/// `prompb/types.proto` in the Prometheus repository
+mod prompb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub timeseries: Vec<TimeSeries>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TimeSeries {
+        #[prost(message, repeated, tag = "1")]
+        pub labels: Vec<Label>,
+
+        #[prost(message, repeated, tag = "2")]
+        pub samples: Vec<Sample>,
+    }
+
+    #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, prost::Message)]
+    pub struct Label {
+        #[prost(string, tag = "1")]
+        pub name: String,
+
+        #[prost(string, tag = "2")]
+        pub value: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Sample {
+        #[prost(double, tag = "1")]
+        pub value: f64,
+
+        /// Milliseconds since the Unix epoch
+        #[prost(int64, tag = "2")]
+        pub timestamp: i64,
+    }
+}
+
+/// Groups the numeric fields of the points into series named `<measurement>_<field>`, labelled
+/// with the tags of the points. Labels and samples are sorted as the protocol requires, the last
+/// point of a series at a given timestamp being kept
+fn time_series(points: &Points) -> Result<Vec<prompb::TimeSeries>, LabelError> {
+    let now = Utc::now();
+    let mut series: BTreeMap<Vec<prompb::Label>, BTreeMap<i64, f64>> = BTreeMap::new();
+
+    for point in points.iter() {
+        let timestamp = point.timestamp.unwrap_or(now).timestamp_millis();
+        let tags = tag_labels(point, &["__name__"])?;
+
+        for (field, value) in &point.fields {
+            let value = match value {
+                Value::Integer(i) => *i as f64,
+                Value::Float(f) => *f,
+                Value::Boolean(b) => {
+                    if *b {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                Value::String(_) => continue,
+            };
+
+            let mut labels: Vec<prompb::Label> = tags
+                .iter()
+                .map(|(name, value)| prompb::Label {
+                    name: name.clone(),
+                    value: value.clone(),
+                })
+                .chain(std::iter::once(prompb::Label {
+                    name: "__name__".to_string(),
+                    value: sanitize(&format!("{}_{}", point.name, field)),
+                }))
+                .collect();
+            labels.sort();
+
+            series.entry(labels).or_default().insert(timestamp, value);
+        }
+    }
+
+    Ok(series
+        .into_iter()
+        .map(|(labels, samples)| prompb::TimeSeries {
+            labels,
+            samples: samples
+                .into_iter()
+                .map(|(timestamp, value)| prompb::Sample { value, timestamp })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Splits series into write requests of about `batch_size` samples, series larger than a batch
+/// being split as well
+fn batches(series: Vec<prompb::TimeSeries>, batch_size: usize) -> Vec<prompb::WriteRequest> {
+    let mut requests = Vec::new();
+    let mut current = prompb::WriteRequest::default();
+    let mut samples = 0;
+
+    for series in series {
+        for chunk in series.samples.chunks(batch_size) {
+            if samples + chunk.len() > batch_size && samples > 0 {
+                requests.push(std::mem::take(&mut current));
+                samples = 0;
+            }
+
+            current.timeseries.push(prompb::TimeSeries {
+                labels: series.labels.clone(),
+                samples: chunk.to_vec(),
+            });
+            samples += chunk.len();
+        }
+    }
+
+    if samples > 0 {
+        requests.push(current);
+    }
+
+    requests
+}
+
+struct RemoteWrite {
+    url: Url,
+
+    auth: Auth,
+
+    batch_size: usize,
+
+    retry: RetryPolicy,
+}
+
+impl RemoteWrite {
+    fn write(
+        &self,
+        client: &reqwest::blocking::Client,
+        request: &prompb::WriteRequest,
+    ) -> SinkResult<()> {
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .map_err(Error::Compress)?;
+
+        debug!(
+            series = request.timeseries.len(),
+            bytes = body.len(),
+            "sending remote write request"
+        );
+
+        let response = self
+            .retry
+            .send("remote write", || {
+                let request = client
+                    .post(self.url.clone())
+                    .header(CONTENT_ENCODING, "snappy")
+                    .header(CONTENT_TYPE, "application/x-protobuf")
+                    .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                    .body(body.clone());
+
+                self.auth.apply(request).send()
+            })
+            .map_err(Error::Request)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response
+                .text()
+                .unwrap_or("Failed to retrieve response text".to_string());
+            return Err(Error::Write(status, body).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for RemoteWrite {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let client = reqwest::blocking::Client::new();
+
+        for request in batches(time_series(points)?, self.batch_size) {
+            self.write(&client, &request)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct Config {
+    /// Remote write endpoint, e.g. `http://localhost:9009/api/v1/push`
+    url: String,
+
+    username: Option<String>,
+
+    password: Option<String>,
+
+    bearer_token: Option<String>,
+
+    /// Maximum number of samples per request
+    batch_size: Option<usize>,
+
+    retry: Option<RetryConfig>,
+}
+
+impl SinkConfig for Config {
+    const RETRY: bool = true;
+
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        let auth = Auth::new(
+            self.username,
+            self.password,
+            self.bearer_token.map(Auth::Bearer),
+            "bearer_token",
+        )?;
+
+        Ok(Box::new(RemoteWrite {
+            url: self.url.parse()?,
+
+            auth,
+
+            batch_size: super::batch_size(self.batch_size, DEFAULT_BATCH_SIZE)?,
+
+            retry: RetryPolicy::try_from(self.retry.unwrap_or_default())?,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("prometheus-remote-write")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{batches, prompb, time_series};
+    use crate::point::{Point, Points, Value};
+    use chrono::{TimeZone, Utc};
+    use prost::Message;
+
+    fn points() -> Points {
+        (0..3)
+            .rev()
+            .map(|hour| {
+                let mut point = Point::builder("eco2mix")
+                    .field("nuclear", Value::Integer(30000 + hour))
+                    .field("level", Value::String("green".to_string()))
+                    .timestamp(
+                        Utc.with_ymd_and_hms(2022, 10, 1, hour as u32, 0, 0)
+                            .unwrap(),
+                    )
+                    .build();
+                point
+                    .tags
+                    .insert("source-name".to_string(), "eco2mix".to_string());
+                point
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_time_series() {
+        let series = time_series(&points()).unwrap();
+        assert_eq!(series.len(), 1);
+
+        let labels: Vec<_> = series[0]
+            .labels
+            .iter()
+            .map(|l| (l.name.as_str(), l.value.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![("__name__", "eco2mix_nuclear"), ("source_name", "eco2mix")]
+        );
+
+        let samples: Vec<_> = series[0]
+            .samples
+            .iter()
+            .map(|s| (s.timestamp, s.value))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![
+                (1664582400000, 30000.0),
+                (1664586000000, 30001.0),
+                (1664589600000, 30002.0)
+            ]
+        );
+
+        let request = prompb::WriteRequest { timeseries: series };
+        let decoded = prompb::WriteRequest::decode(request.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut duplicated = points();
+        duplicated.merge_with(points());
+        let series = time_series(&duplicated).unwrap();
+        assert_eq!(series[0].samples.len(), 3);
+
+        let mut colliding = points();
+        colliding.tag_all("__name__", "eco2mix");
+        assert!(time_series(&colliding).is_err());
+
+        let mut colliding = points();
+        colliding.tag_all("source_name", "eco2mix");
+        assert!(time_series(&colliding).is_err());
+    }
+
+    #[test]
+    fn test_batches() {
+        let requests = batches(time_series(&points()).unwrap(), 2);
+        let sizes: Vec<usize> = requests
+            .iter()
+            .map(|r| r.timeseries.iter().map(|s| s.samples.len()).sum())
+            .collect();
+
+        assert_eq!(sizes, vec![2, 1]);
+    }
+}
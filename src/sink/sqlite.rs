@@ -0,0 +1,493 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::Mutex,
+};
+
+use chrono::Utc;
+use rusqlite::{params_from_iter, types, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    point::{Point, Points, Value},
+    sink::Registration,
+};
+
+use super::{Sink, SinkConfig, SinkResult};
+
+const DEFAULT_TABLE: &str = "points";
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to open database {1}")]
+    Open(#[source] rusqlite::Error, String),
+
+    #[error("failed to write measurement {1}")]
+    Query(#[source] rusqlite::Error, String),
+
+    #[error("column {0} of measurement {1} is both a tag and a field")]
+    DuplicateColumn(String, String),
+
+    #[error("timestamp of measurement {0} is out of range")]
+    Timestamp(String),
+
+    #[error("index {0} of measurement {1} collides with another table or index")]
+    IndexName(String, String),
+}
+
+/// How points are laid out in the database
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Layout {
+    /// One table per measurement, with a column per tag and per field
+    #[default]
+    Wide,
+
+    /// A single table with a row per field of every point
+    Long,
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Fails when `index` already names an object other than an index of `table`, tables and indexes
+/// sharing a namespace
+fn check_index(tx: &Transaction, index: &str, table: &str) -> Result<(), Error> {
+    let owner = tx
+        .query_row(
+            "SELECT type, tbl_name FROM sqlite_master WHERE name = ?1 COLLATE NOCASE",
+            [index],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(|e| Error::Query(e, table.to_string()))?;
+
+    match owner {
+        Some((kind, name)) if kind != "index" || !name.eq_ignore_ascii_case(table) => {
+            Err(Error::IndexName(index.to_string(), table.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn affinity(value: &Value) -> &'static str {
+    match value {
+        Value::Integer(_) => "INTEGER",
+        Value::Float(_) => "REAL",
+        Value::Boolean(_) => "BOOLEAN",
+        Value::String(_) => "TEXT",
+    }
+}
+
+fn sql_value(value: &Value) -> types::Value {
+    match value {
+        Value::Integer(i) => types::Value::Integer(*i),
+        Value::Float(f) => types::Value::Real(*f),
+        Value::Boolean(b) => types::Value::Integer(*b as i64),
+        Value::String(s) => types::Value::Text(s.clone()),
+    }
+}
+
+/// Timestamp of a point in nanoseconds since the Unix epoch
+fn timestamp(point: &Point, now: i64) -> Result<i64, Error> {
+    match point.timestamp {
+        Some(ts) => ts
+            .timestamp_nanos_opt()
+            .ok_or_else(|| Error::Timestamp(point.name.clone())),
+        None => Ok(now),
+    }
+}
+
+struct Sqlite {
+    layout: Layout,
+
+    table: String,
+
+    connection: Mutex<Connection>,
+}
+
+impl Sqlite {
+    /// Adds the missing tag and field columns of a measurement table, creating it if needed, and
+    /// returns its tags
+    fn prepare_wide(
+        tx: &Transaction,
+        measurement: &str,
+        points: &[&Point],
+    ) -> Result<Vec<String>, Error> {
+        let query_error = |e| Error::Query(e, measurement.to_string());
+        let table = quote(measurement);
+
+        tx.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {table} (time INTEGER NOT NULL)"
+        ))
+        .map_err(query_error)?;
+
+        // Tags are the non-null text columns besides the time
+        let mut tags = BTreeSet::new();
+        let mut fields = BTreeSet::new();
+        {
+            let mut statement = tx
+                .prepare(&format!("PRAGMA table_info({table})"))
+                .map_err(query_error)?;
+            let columns = statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, bool>(3)?,
+                    ))
+                })
+                .map_err(query_error)?;
+
+            for column in columns {
+                let (name, column_type, not_null) = column.map_err(query_error)?;
+                if name == "time" {
+                    continue;
+                }
+
+                if not_null && column_type == "TEXT" {
+                    tags.insert(name);
+                } else {
+                    fields.insert(name);
+                }
+            }
+        }
+
+        let mut new_tag = false;
+        for tag in points.iter().flat_map(|p| p.tags.keys()) {
+            if !tags.contains(tag) {
+                tx.execute_batch(&format!(
+                    "ALTER TABLE {table} ADD COLUMN {} TEXT NOT NULL DEFAULT ''",
+                    quote(tag)
+                ))
+                .map_err(query_error)?;
+
+                tags.insert(tag.clone());
+                new_tag = true;
+            }
+        }
+
+        for (field, value) in points.iter().flat_map(|p| &p.fields) {
+            if tags.contains(field) {
+                return Err(Error::DuplicateColumn(
+                    field.clone(),
+                    measurement.to_string(),
+                ));
+            }
+
+            if fields.insert(field.clone()) {
+                tx.execute_batch(&format!(
+                    "ALTER TABLE {table} ADD COLUMN {} {}",
+                    quote(field),
+                    affinity(value)
+                ))
+                .map_err(query_error)?;
+            }
+        }
+
+        // Upserts need a unique index covering the time and every tag, which also serves queries
+        // on the time
+        let index = format!("idx_{measurement}_upsert");
+        check_index(tx, &index, measurement)?;
+
+        let index = quote(&index);
+        let columns = std::iter::once("time".to_string())
+            .chain(tags.iter().map(|t| quote(t)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if new_tag {
+            tx.execute_batch(&format!("DROP INDEX IF EXISTS {index}"))
+                .map_err(query_error)?;
+        }
+        tx.execute_batch(&format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {index} ON {table} ({columns})"
+        ))
+        .map_err(query_error)?;
+
+        Ok(tags.into_iter().collect())
+    }
+
+    fn write_wide(
+        tx: &Transaction,
+        measurement: &str,
+        points: &[&Point],
+        now: i64,
+    ) -> Result<(), Error> {
+        let tags = Self::prepare_wide(tx, measurement, points)?;
+        let fields: Vec<&String> = points
+            .iter()
+            .flat_map(|p| p.fields.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let columns: Vec<String> = std::iter::once("time".to_string())
+            .chain(tags.iter().map(|t| quote(t)))
+            .chain(fields.iter().map(|f| quote(f)))
+            .collect();
+        let conflict = columns[..1 + tags.len()].join(", ");
+        let action = if fields.is_empty() {
+            "NOTHING".to_string()
+        } else {
+            // Fields missing from a point keep their previous value
+            let updates = fields
+                .iter()
+                .map(|f| {
+                    let f = quote(f);
+                    format!("{f} = COALESCE(excluded.{f}, {f})")
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("UPDATE SET {updates}")
+        };
+
+        let query_error = |e| Error::Query(e, measurement.to_string());
+        let mut statement = tx
+            .prepare(&format!(
+                "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({conflict}) DO {action}",
+                quote(measurement),
+                columns.join(", "),
+                vec!["?"; columns.len()].join(", "),
+            ))
+            .map_err(query_error)?;
+
+        for point in points {
+            let row =
+                std::iter::once(types::Value::Integer(timestamp(point, now)?))
+                    .chain(tags.iter().map(|t| {
+                        types::Value::Text(point.tags.get(t).cloned().unwrap_or_default())
+                    }))
+                    .chain(
+                        fields
+                            .iter()
+                            .map(|f| point.fields.get(*f).map_or(types::Value::Null, sql_value)),
+                    );
+
+            statement
+                .execute(params_from_iter(row))
+                .map_err(query_error)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_long(&self, tx: &Transaction, points: &Points, now: i64) -> Result<(), Error> {
+        let table = quote(&self.table);
+        let query_error = |e| Error::Query(e, self.table.clone());
+
+        tx.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {table} (
+                measurement TEXT NOT NULL,
+                tags TEXT NOT NULL,
+                field TEXT NOT NULL,
+                value,
+                time INTEGER NOT NULL
+            );
+            CREATE UNIQUE INDEX IF NOT EXISTS {} ON {table} (measurement, tags, field, time);
+            CREATE INDEX IF NOT EXISTS {} ON {table} (time);",
+            quote(&format!("{}_upsert", self.table)),
+            quote(&format!("{}_time", self.table)),
+        ))
+        .map_err(query_error)?;
+
+        let mut statement = tx
+            .prepare(&format!(
+                "INSERT INTO {table} (measurement, tags, field, value, time) VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT (measurement, tags, field, time) DO UPDATE SET value = excluded.value"
+            ))
+            .map_err(query_error)?;
+
+        for point in points.iter() {
+            // Tags are stored as a JSON object with sorted keys so that equal tags always match
+            let tags = serde_json::to_string(&point.tags.iter().collect::<BTreeMap<_, _>>())
+                .expect("tags are serializable");
+            let time = timestamp(point, now)?;
+
+            for (field, value) in &point.fields {
+                statement
+                    .execute((&point.name, &tags, field, sql_value(value), time))
+                    .map_err(query_error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for Sqlite {
+    fn sink(&self, points: &Points) -> SinkResult<()> {
+        let now = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+
+        let mut connection = self.connection.lock().expect("poisoned lock");
+        let tx = connection
+            .transaction()
+            .map_err(|e| Error::Query(e, "transaction".to_string()))?;
+
+        debug!(points = points.len(), "writing points");
+
+        match self.layout {
+            Layout::Wide => {
+                let mut measurements: BTreeMap<&str, Vec<&Point>> = BTreeMap::new();
+                for point in points.iter() {
+                    measurements.entry(&point.name).or_default().push(point);
+                }
+
+                for (measurement, points) in measurements {
+                    Self::write_wide(&tx, measurement, &points, now)?;
+                }
+            }
+            Layout::Long => self.write_long(&tx, points, now)?,
+        }
+
+        tx.commit()
+            .map_err(|e| Error::Query(e, "transaction".to_string()).into())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    /// Database file, created if needed
+    path: String,
+
+    layout: Option<Layout>,
+
+    /// Table of the long layout
+    table: Option<String>,
+}
+
+impl SinkConfig for Config {
+    fn build(self) -> SinkResult<Box<dyn Sink>> {
+        if let Some(parent) = Path::new(&self.path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(&self.path).map_err(|e| Error::Open(e, self.path))?;
+
+        Ok(Box::new(Sqlite {
+            layout: self.layout.unwrap_or_default(),
+
+            table: self.table.unwrap_or(DEFAULT_TABLE.to_string()),
+
+            connection: Mutex::new(connection),
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("sqlite")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Layout, Sqlite};
+    use crate::{
+        point::{Point, Points, Value},
+        sink::Sink,
+    };
+    use chrono::{TimeZone, Utc};
+    use rusqlite::Connection;
+    use std::sync::Mutex;
+
+    fn sink(layout: Layout) -> Sqlite {
+        Sqlite {
+            layout,
+            table: "points".to_string(),
+            connection: Mutex::new(Connection::open_in_memory().unwrap()),
+        }
+    }
+
+    fn points(nuclear: i64) -> Points {
+        let timestamp = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(nuclear))
+            .field("solar", Value::Float(1500.5))
+            .timestamp(timestamp)
+            .build();
+        point
+            .tags
+            .insert("source".to_string(), "eco2mix".to_string());
+
+        let untagged = Point::builder("eco2mix")
+            .field("nuclear", Value::Integer(nuclear))
+            .timestamp(timestamp)
+            .build();
+
+        vec![point, untagged].into()
+    }
+
+    fn query(sink: &Sqlite, sql: &str) -> Vec<(String, i64)> {
+        let connection = sink.connection.lock().unwrap();
+        let mut statement = connection.prepare(sql).unwrap();
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_wide_layout() {
+        let sink = sink(Layout::Wide);
+
+        sink.sink(&points(30000)).unwrap();
+        sink.sink(&points(31000)).unwrap();
+
+        assert_eq!(
+            query(&sink, "SELECT source, nuclear FROM eco2mix ORDER BY source"),
+            vec![("".to_string(), 31000), ("eco2mix".to_string(), 31000)]
+        );
+    }
+
+    #[test]
+    fn test_index_names() {
+        let sink = sink(Layout::Wide);
+        let timestamp = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+
+        // The index of eco2mix does not collide with the table of eco2mix_upsert
+        let mut batch = points(30000);
+        batch.add(
+            Point::builder("eco2mix_upsert")
+                .field("nuclear", Value::Integer(30000))
+                .timestamp(timestamp)
+                .build(),
+        );
+        sink.sink(&batch).unwrap();
+
+        sink.connection
+            .lock()
+            .unwrap()
+            .execute_batch("CREATE TABLE idx_ecowatt_upsert (time INTEGER)")
+            .unwrap();
+        let ecowatt = Point::builder("ecowatt")
+            .field("value", Value::Integer(1))
+            .timestamp(timestamp)
+            .build();
+        assert!(sink.sink(&vec![ecowatt].into()).is_err());
+    }
+
+    #[test]
+    fn test_long_layout() {
+        let sink = sink(Layout::Long);
+
+        sink.sink(&points(30000)).unwrap();
+        sink.sink(&points(31000)).unwrap();
+
+        assert_eq!(
+            query(
+                &sink,
+                "SELECT tags, value FROM points WHERE field = 'nuclear' ORDER BY tags"
+            ),
+            vec![
+                ("{\"source\":\"eco2mix\"}".to_string(), 31000),
+                ("{}".to_string(), 31000)
+            ]
+        );
+        assert_eq!(
+            query(&sink, "SELECT field, COUNT(*) FROM points GROUP BY field"),
+            vec![("nuclear".to_string(), 2), ("solar".to_string(), 1)]
+        );
+    }
+}
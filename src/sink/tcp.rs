@@ -0,0 +1,165 @@
+use std::{
+    io::{BufWriter, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    time::Duration,
+};
+
+use thiserror::Error;
+use tracing::{debug, warn};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("failed to connect to {1}: {0}")]
+    Connect(#[source] std::io::Error, String),
+
+    #[error("failed to establish a TLS session with {1}: {0}")]
+    Tls(#[source] Box<dyn std::error::Error + Send + Sync>, String),
+
+    #[error("failed to write to {1}: {0}")]
+    Write(#[source] std::io::Error, String),
+}
+
+/// Host of a `host:port` address, used as the TLS server name, without the brackets of IPv6
+/// literals
+fn server_name(address: &str) -> &str {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+struct Connection {
+    writer: BufWriter<Box<dyn Write + Send>>,
+
+    /// Handle on the underlying socket, used to notice connections closed by the listener
+    socket: TcpStream,
+}
+
+impl Connection {
+    /// Whether the listener closed the connection, which writes would otherwise only notice
+    /// once the data of the first write is lost
+    fn closed(&self) -> bool {
+        let closed = self.socket.set_nonblocking(true).is_ok()
+            && matches!(self.socket.peek(&mut [0; 1]), Ok(0));
+        let _ = self.socket.set_nonblocking(false);
+        closed
+    }
+}
+
+/// Persistent, optionally TLS, connection to a listener of line-based protocols
+pub struct LineWriter {
+    /// `host:port` of the listener
+    address: String,
+
+    tls: bool,
+
+    timeout: Duration,
+
+    connection: Mutex<Option<Connection>>,
+}
+
+impl LineWriter {
+    pub fn new(address: String, tls: bool, timeout: Duration) -> Self {
+        Self {
+            address,
+            tls,
+            timeout,
+            connection: Mutex::new(None),
+        }
+    }
+
+    fn connect(&self) -> Result<Connection, Error> {
+        debug!(address = self.address, tls = self.tls, "connecting");
+
+        let addr = self
+            .address
+            .to_socket_addrs()
+            .and_then(|mut addrs| {
+                addrs
+                    .next()
+                    .ok_or_else(|| std::io::Error::other("no address resolved"))
+            })
+            .map_err(|e| Error::Connect(e, self.address.clone()))?;
+
+        // The read timeout bounds the TLS handshake, nothing is read afterwards
+        let stream = TcpStream::connect_timeout(&addr, self.timeout)
+            .and_then(|s| s.set_write_timeout(Some(self.timeout)).map(|_| s))
+            .and_then(|s| s.set_read_timeout(Some(self.timeout)).map(|_| s))
+            .map_err(|e| Error::Connect(e, self.address.clone()))?;
+
+        let socket = stream
+            .try_clone()
+            .map_err(|e| Error::Connect(e, self.address.clone()))?;
+
+        let stream: Box<dyn Write + Send> = if self.tls {
+            let stream = native_tls::TlsConnector::new()
+                .map_err(|e| Error::Tls(e.into(), self.address.clone()))?
+                .connect(server_name(&self.address), stream)
+                .map_err(|e| Error::Tls(e.to_string().into(), self.address.clone()))?;
+            Box::new(stream)
+        } else {
+            Box::new(stream)
+        };
+
+        Ok(Connection {
+            writer: BufWriter::new(stream),
+
+            socket,
+        })
+    }
+
+    /// Writes the lines and flushes the connection, connecting first if needed
+    fn write(&self, connection: &mut Option<Connection>, lines: &[String]) -> Result<(), Error> {
+        if connection.as_ref().is_some_and(Connection::closed) {
+            debug!(address = self.address, "connection closed by the listener");
+            *connection = None;
+        }
+
+        let writer = match connection {
+            Some(connection) => &mut connection.writer,
+            None => &mut connection.insert(self.connect()?).writer,
+        };
+
+        lines
+            .iter()
+            .try_for_each(|line| writeln!(writer, "{line}"))
+            .and_then(|_| writer.flush())
+            .map_err(|e| Error::Write(e, self.address.clone()))
+    }
+
+    /// Writes the lines, flushing the connection every `batch_size` lines
+    pub fn send(&self, lines: &[String], batch_size: usize) -> Result<(), Error> {
+        let mut connection = self.connection.lock().expect("poisoned lock");
+
+        for batch in lines.chunks(batch_size) {
+            // A batch that failed to be written, e.g. on a connection reset, is written again
+            // once on a new connection. Part of it may have been received already, making the
+            // delivery at least once
+            if let Err(e) = self.write(&mut connection, batch) {
+                warn!("{e}, reconnecting");
+                *connection = None;
+
+                if let Err(e) = self.write(&mut connection, batch) {
+                    *connection = None;
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::server_name;
+
+    #[test]
+    fn test_server_name() {
+        assert_eq!(server_name("localhost:9009"), "localhost");
+        assert_eq!(server_name("[::1]:2003"), "::1");
+        assert_eq!(server_name("localhost"), "localhost");
+    }
+}
@@ -4,7 +4,7 @@ use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::point::Points;
+use crate::{point::Points, retry};
 
 pub mod rte;
 
@@ -22,12 +22,15 @@ pub enum Error {
 
 pub type DataSourceResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-pub trait DataSource {
-    fn collect(&self) -> DataSourceResult<Points>;
+pub trait DataSource: Send + Sync {
+    fn collect(&self, global: &GlobalConfig) -> DataSourceResult<Points>;
 }
 
 pub trait DataSourceConfig: Send + Sync {
-    fn build(self, global: GlobalConfig) -> DataSourceResult<Box<dyn DataSource>>;
+    /// Whether the configuration has a `retry` table, filled with the global `[retry]` settings
+    const RETRY: bool = false;
+
+    fn build(self) -> DataSourceResult<Box<dyn DataSource>>;
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -37,10 +40,14 @@ pub struct GlobalConfig {
     pub to_date: NaiveDate,
 }
 
+type Builder = fn(&str, toml::Value) -> Result<Box<dyn DataSource>, Error>;
+
 pub struct Registration {
     name: &'static str,
 
-    builder: fn(&str, toml::Value, GlobalConfig) -> Result<Box<dyn DataSource>, Error>,
+    builder: Builder,
+
+    retry: bool,
 }
 
 impl Registration {
@@ -48,20 +55,24 @@ impl Registration {
     where
         DSC: DataSourceConfig + serde::Deserialize<'a>,
     {
-        let builder = |name: &str, value: toml::Value, global: GlobalConfig| {
+        let builder = |name: &str, value: toml::Value| {
             let config: DSC = value.try_into().map_err(Error::Toml)?;
             config
-                .build(global)
+                .build()
                 .map_err(|e| Error::Config(e, name.to_string()))
         };
 
-        Self { name, builder }
+        Self {
+            name,
+            builder,
+            retry: DSC::RETRY,
+        }
     }
 
     pub fn build(
         name: &str,
-        value: toml::Value,
-        global_config: GlobalConfig,
+        mut value: toml::Value,
+        global_retry: Option<&toml::Value>,
     ) -> Result<Box<dyn DataSource>, Error> {
         let registrations: HashMap<&'static str, &Registration> = inventory::iter::<Registration>()
             .map(|r| (r.name, r))
@@ -71,9 +82,13 @@ impl Registration {
             .get(name)
             .ok_or(Error::Unknown(name.to_string()))
             .and_then(|r| {
+                if r.retry {
+                    retry::merge(&mut value, global_retry);
+                }
+
                 let builder = r.builder;
 
-                builder(name, value, global_config)
+                builder(name, value)
             })
     }
 }
@@ -5,7 +5,7 @@ use std::{
     str::FromStr,
 };
 
-use chrono::prelude::*;
+use chrono::{prelude::*, LocalResult};
 use chrono_tz::{Europe::Paris, Tz};
 use csv::ByteRecord;
 use serde::{Deserialize, Serialize};
@@ -14,13 +14,16 @@ use tracing::{field, info};
 
 use crate::{
     point::{Point, Points, Value},
+    pool,
+    retry::{RetryConfig, RetryPolicy},
     value,
 };
 
 use crate::source::{DataSource, DataSourceConfig, DataSourceResult, GlobalConfig, Registration};
 
-const END_RECORD: &'static [u8] = b"RTE ne pourra";
+const END_RECORD: &[u8] = b"RTE ne pourra";
 const ECO2MIX_DATA_URL: &str = "https://eco2mix.rte-france.com/curves/eco2mixDl";
+const DEFAULT_CONCURRENCY: usize = 4;
 
 struct DaysIterator(NaiveDate, NaiveDate);
 
@@ -30,7 +33,7 @@ impl Iterator for DaysIterator {
     fn next(&mut self) -> Option<Self::Item> {
         if self.0 <= self.1 {
             let curr = self.0;
-            let next = curr.succ();
+            let next = curr.succ_opt().expect("date out of range");
 
             self.0 = next;
             Some(curr)
@@ -86,8 +89,11 @@ enum DataError {
     #[error("invalid date format: {0}")]
     Date(#[source] chrono::ParseError),
 
+    #[error("nonexistent local time {0}")]
+    LocalTime(NaiveDateTime),
+
     #[error("error parsing field {1}: {0}")]
-    Parse(#[source] Box<dyn std::error::Error>, String),
+    Parse(#[source] Box<dyn std::error::Error + Send + Sync>, String),
 }
 
 #[derive(Error, Debug)]
@@ -106,7 +112,9 @@ enum Error {
 struct DailyRow {
     date: DateTime<Tz>,
     generation_total: u64,
+    #[allow(dead_code)]
     prediction_yesterday: u64,
+    #[allow(dead_code)]
     prediction_now: u64,
     oil: u64,
     coal: u64,
@@ -121,7 +129,13 @@ struct DailyRow {
 }
 
 impl DailyRow {
-    fn from_record(record: ByteRecord) -> Result<DailyRow, DataError> {
+    /// Parses a row of the data file, `previous` being the time of the row before it. The hour
+    /// repeated by the autumn DST change appears twice in the file, the second time being given
+    /// the later offset
+    fn from_record(
+        record: ByteRecord,
+        previous: Option<DateTime<Tz>>,
+    ) -> Result<DailyRow, DataError> {
         let scope = record
             .get(0)
             .ok_or(DataError::MissingField("Perimetre".to_string()))?;
@@ -144,11 +158,15 @@ impl DailyRow {
             .map(String::from_utf8_lossy)
             .and_then(|s| NaiveTime::parse_from_str(&s, "%H:%M").map_err(DataError::Date))?;
 
-        let dt = Paris.ymd(date.year(), date.month(), date.day()).and_hms(
-            time.hour(),
-            time.minute(),
-            time.second(),
-        );
+        let local = date.and_time(time);
+        let dt = match Paris.from_local_datetime(&local) {
+            LocalResult::Single(dt) => dt,
+            LocalResult::Ambiguous(earliest, latest) => match previous {
+                Some(previous) if previous >= earliest => latest,
+                _ => earliest,
+            },
+            LocalResult::None => return Err(DataError::LocalTime(local)),
+        };
 
         Ok(DailyRow {
             date: dt,
@@ -170,7 +188,7 @@ impl DailyRow {
 
     fn get_field<S: FromStr>(record: &ByteRecord, index: usize, name: &str) -> Result<S, DataError>
     where
-        S::Err: std::error::Error + 'static,
+        S::Err: std::error::Error + Send + Sync + 'static,
     {
         record
             .get(index)
@@ -184,7 +202,7 @@ fn format_url(date: NaiveDate) -> String {
     format!("{ECO2MIX_DATA_URL}?date={}", date.format("%d/%m/%Y"))
 }
 
-fn download(date: NaiveDate, folder: &Path) -> Result<PathBuf, DownloadError> {
+fn download(date: NaiveDate, folder: &Path, retry: &RetryPolicy) -> Result<PathBuf, DownloadError> {
     let url = format_url(date);
 
     let mut file_path = folder.to_path_buf();
@@ -198,20 +216,30 @@ fn download(date: NaiveDate, folder: &Path) -> Result<PathBuf, DownloadError> {
 
     let mut file =
         File::create(&file_path).map_err(|e| DownloadError::CreateFile(e, file_path.clone()))?;
-    let mut response = reqwest::blocking::get(url).map_err(DownloadError::Http)?;
+    let client = reqwest::blocking::Client::new();
+    let mut response = retry
+        .send("eco2mix download", || client.get(&url).send())
+        .and_then(|r| r.error_for_status())
+        .map_err(DownloadError::Http)?;
     response.copy_to(&mut file).map_err(DownloadError::Io)?;
 
     Ok(file_path)
 }
 
 fn extract(path: impl AsRef<Path>) -> Result<PathBuf, ExtractionError> {
-    let file = std::fs::File::open(path).map_err(ExtractionError::OpenFile)?;
+    let file = std::fs::File::open(path.as_ref()).map_err(ExtractionError::OpenFile)?;
     let mut archive = zip::ZipArchive::new(file).map_err(ExtractionError::Zip)?;
     let mut file = archive.by_index(0).map_err(ExtractionError::Zip)?;
-    let out_path = file
+
+    // Extract next to the archive, which is named after the day, so that days downloaded
+    // concurrently never write to the same file
+    let extension = file
         .enclosed_name()
         .ok_or(ExtractionError::FileNotFound)?
-        .to_owned();
+        .extension()
+        .map(|e| e.to_owned())
+        .unwrap_or_else(|| "xls".into());
+    let out_path = path.as_ref().with_extension(extension);
 
     info!(
         out_path = field::display(out_path.display()),
@@ -242,7 +270,8 @@ fn read(path: impl AsRef<Path>) -> Result<Vec<DailyRow>, DataError> {
             break;
         }
 
-        rows.push(DailyRow::from_record(record)?);
+        let previous = rows.last().map(|r: &DailyRow| r.date);
+        rows.push(DailyRow::from_record(record, previous)?);
     }
 
     Ok(rows)
@@ -284,56 +313,78 @@ fn is_end_record(record: &ByteRecord) -> bool {
         .unwrap_or(false)
 }
 
+fn collect_day(
+    date: NaiveDate,
+    download_folder: &Path,
+    retry: &RetryPolicy,
+) -> Result<Points, Error> {
+    info!("collecting date for {date}");
+
+    download(date, download_folder, retry)
+        .map_err(Error::Download)
+        .and_then(|file_path| extract(file_path).map_err(Error::Extraction))
+        .and_then(|file_path| read(file_path).map_err(Error::Data))
+        .map(|lines| lines.into())
+}
+
 fn collect(
     global_config: &GlobalConfig,
     download_folder: impl AsRef<Path>,
+    concurrency: usize,
+    retry: &RetryPolicy,
 ) -> Result<Points, Error> {
+    let days: Vec<_> = iter_days(global_config.from_date, global_config.to_date).collect();
+    let download_folder = download_folder.as_ref();
     let mut points = Points::new();
 
-    for date in iter_days(global_config.from_date, global_config.to_date) {
-        info!("collecting date for {date}");
-
-        let day_points: Points = download(date, download_folder.as_ref())
-            .map_err(Error::Download)
-            .and_then(|file_path| extract(file_path).map_err(Error::Extraction))
-            .and_then(|file_path| read(file_path).map_err(Error::Data))
-            .map(|lines| lines.into())?;
-
-        points.merge_with(day_points);
+    for day_points in pool::map_ordered(&days, concurrency, |date| {
+        collect_day(*date, download_folder, retry)
+    }) {
+        points.merge_with(day_points?);
     }
 
     Ok(points)
 }
 
 struct Rte {
-    global: GlobalConfig,
-
     download_folder: Option<String>,
+
+    concurrency: usize,
+
+    retry: RetryPolicy,
 }
 
 #[derive(Serialize, Deserialize)]
 struct Config {
     download_folder: Option<String>,
+
+    /// Maximum number of days downloaded concurrently
+    concurrency: Option<usize>,
+
+    retry: Option<RetryConfig>,
 }
 
 impl DataSourceConfig for Config {
-    fn build(self, global: GlobalConfig) -> DataSourceResult<Box<dyn DataSource>> {
+    const RETRY: bool = true;
+
+    fn build(self) -> DataSourceResult<Box<dyn DataSource>> {
         Ok(Box::new(Rte {
-            global,
             download_folder: self.download_folder,
+            concurrency: self.concurrency.unwrap_or(DEFAULT_CONCURRENCY),
+            retry: RetryPolicy::try_from(self.retry.unwrap_or_default())?,
         }))
     }
 }
 
 impl DataSource for Rte {
-    fn collect(&self) -> DataSourceResult<Points> {
+    fn collect(&self, global: &GlobalConfig) -> DataSourceResult<Points> {
         let download_folder = self
             .download_folder
             .as_ref()
             .map(PathBuf::from)
             .unwrap_or(std::env::temp_dir());
 
-        collect(&self.global, download_folder).map_err(Into::into)
+        collect(global, download_folder, self.concurrency, &self.retry).map_err(Into::into)
     }
 }
 
@@ -343,24 +394,57 @@ inventory::submit! {
 
 #[cfg(test)]
 mod test {
-    use super::iter_days;
-    use chrono::NaiveDate;
+    use super::{iter_days, read};
+    use chrono::{NaiveDate, TimeZone, Utc};
 
-    fn assert_day_range(from: NaiveDate, to: NaiveDate, expected: Vec<(NaiveDate, NaiveDate)>) {
+    fn assert_day_range(from: NaiveDate, to: NaiveDate, expected: Vec<NaiveDate>) {
         assert_eq!(iter_days(from, to).collect::<Vec<_>>(), expected);
     }
 
 
     #[test]
     fn test_day_range() {
-        assert_day_range(NaiveDate::from_ymd(2022, 5, 1), NaiveDate::from_ymd(2022, 5, 2), vec![
-            (NaiveDate::from_ymd(2022, 5, 1), NaiveDate::from_ymd(2022, 5, 2)),
+        assert_day_range(NaiveDate::from_ymd_opt(2022, 5, 1).unwrap(), NaiveDate::from_ymd_opt(2022, 5, 2).unwrap(), vec![
+            NaiveDate::from_ymd_opt(2022, 5, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 5, 2).unwrap(),
         ]);
 
-        assert_day_range(NaiveDate::from_ymd(2022, 5, 1), NaiveDate::from_ymd(2022, 5, 4), vec![
-            (NaiveDate::from_ymd(2022, 5, 1), NaiveDate::from_ymd(2022, 5, 2)),
-            (NaiveDate::from_ymd(2022, 5, 2), NaiveDate::from_ymd(2022, 5, 3)),
-            (NaiveDate::from_ymd(2022, 5, 3), NaiveDate::from_ymd(2022, 5, 4)),
+        assert_day_range(NaiveDate::from_ymd_opt(2022, 5, 1).unwrap(), NaiveDate::from_ymd_opt(2022, 5, 4).unwrap(), vec![
+            NaiveDate::from_ymd_opt(2022, 5, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 5, 2).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 5, 3).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 5, 4).unwrap(),
         ]);
+
+        assert_day_range(NaiveDate::from_ymd_opt(2022, 5, 2).unwrap(), NaiveDate::from_ymd_opt(2022, 5, 1).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_read_repeated_hour() {
+        let path = std::env::temp_dir().join(format!("photon-eco2mix-{}.xls", std::process::id()));
+        let rows: String = ["01:45", "02:00", "02:45", "02:00", "02:45", "03:00"]
+            .iter()
+            .map(|time| format!("France\tDonnées\t2022-10-30\t{time}{}\n", "\t0".repeat(14)))
+            .collect();
+        std::fs::write(&path, format!("Périmètre\tNature\tDate\tHeures\n{rows}")).unwrap();
+
+        let times: Vec<_> = read(&path)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.date.with_timezone(&Utc))
+            .collect();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            times,
+            vec![
+                Utc.with_ymd_and_hms(2022, 10, 29, 23, 45, 0).unwrap(),
+                Utc.with_ymd_and_hms(2022, 10, 30, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2022, 10, 30, 0, 45, 0).unwrap(),
+                Utc.with_ymd_and_hms(2022, 10, 30, 1, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2022, 10, 30, 1, 45, 0).unwrap(),
+                Utc.with_ymd_and_hms(2022, 10, 30, 2, 0, 0).unwrap(),
+            ]
+        );
     }
 }
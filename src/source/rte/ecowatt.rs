@@ -1,17 +1,16 @@
 use chrono::{prelude::*, Duration};
-use chrono_tz::{Europe::Paris, Tz};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
-    point::{self, Points, Value},
+    point::{self, Value},
+    retry::{RetryConfig, RetryPolicy},
     source::{DataSource, DataSourceConfig, DataSourceResult, GlobalConfig, Registration},
     value,
 };
 
-const ECOWATT_URL: &'static str =
-    "https://digital.iservices.rte-france.com/open_api/ecowatt/v4/signals";
-const ECOWATT_SANDBOX_URL: &'static str =
+const ECOWATT_URL: &str = "https://digital.iservices.rte-france.com/open_api/ecowatt/v4/signals";
+const ECOWATT_SANDBOX_URL: &str =
     "https://digital.iservices.rte-france.com/open_api/ecowatt/v4/sandbox/signals";
 
 #[derive(Error, Debug)]
@@ -37,8 +36,6 @@ struct EcoWattSignal {
     #[serde(rename = "dvalue")]
     day_value: u32,
 
-    message: String,
-
     values: Vec<EcoWattValue>,
 }
 
@@ -48,11 +45,11 @@ struct EcowattResponse {
 }
 
 struct EcoWatt {
-    global: GlobalConfig,
-
     token: String,
 
     url: String,
+
+    retry: RetryPolicy,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -60,10 +57,14 @@ struct Config {
     token: String,
 
     sandbox: Option<bool>,
+
+    retry: Option<RetryConfig>,
 }
 
 impl DataSourceConfig for Config {
-    fn build(self, global: GlobalConfig) -> DataSourceResult<Box<dyn DataSource>> {
+    const RETRY: bool = true;
+
+    fn build(self) -> DataSourceResult<Box<dyn DataSource>> {
         let url = match self.sandbox {
             Some(true) => ECOWATT_SANDBOX_URL,
             _ => ECOWATT_URL,
@@ -71,25 +72,26 @@ impl DataSourceConfig for Config {
         .to_string();
 
         Ok(Box::new(EcoWatt {
-            global,
             token: self.token,
             url,
+            retry: RetryPolicy::try_from(self.retry.unwrap_or_default())?,
         }))
     }
 }
 
 impl DataSource for EcoWatt {
-    fn collect(&self) -> DataSourceResult<point::Points> {
-        let response = reqwest::blocking::Client::builder()
-            .build()?
-            .get(&self.url)
-            .bearer_auth(self.token.clone())
-            .send()?
+    fn collect(&self, _global: &GlobalConfig) -> DataSourceResult<point::Points> {
+        let client = reqwest::blocking::Client::builder().build()?;
+        let response = self
+            .retry
+            .send("ecowatt request", || {
+                client.get(&self.url).bearer_auth(&self.token).send()
+            })?
             .error_for_status()?
             .json::<EcowattResponse>()?;
 
-        let today_signal = response.signals.get(0).ok_or(Error::NoSignal)?;
-        let mut points = point::Points::new();
+        let today_signal = response.signals.first().ok_or(Error::NoSignal)?;
+        let mut points = point::Points::with_capacity(today_signal.values.len() + 1);
 
         points.add(
             point::Point::builder("ecowatt_signal")
@@ -1 +1,2 @@
 mod eco2mix;
+mod ecowatt;
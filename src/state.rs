@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const STATE_FILE: &str = "state.json";
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("error creating state directory {1}: {0}")]
+    CreateDir(#[source] std::io::Error, PathBuf),
+
+    #[error("error reading state file {1}: {0}")]
+    Read(#[source] std::io::Error, PathBuf),
+
+    #[error("error writing state file {1}: {0}")]
+    Write(#[source] std::io::Error, PathBuf),
+
+    #[error("invalid state file {1}: {0}")]
+    Json(#[source] serde_json::Error, PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SourceState {
+    /// Last day whose points were delivered to every sink the source is routed to
+    pub last_date: NaiveDate,
+
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct State {
+    sources: HashMap<String, SourceState>,
+}
+
+/// Checkpoints of the data source instances, persisted as JSON in a state directory
+pub struct StateStore {
+    path: PathBuf,
+
+    state: State,
+}
+
+impl StateStore {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(|e| Error::CreateDir(e, dir.to_path_buf()))?;
+
+        let path = dir.join(STATE_FILE);
+        let state = match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                serde_json::from_str(&content).map_err(|e| Error::Json(e, path.clone()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => return Err(Error::Read(e, path)),
+        };
+
+        Ok(Self { path, state })
+    }
+
+    pub fn last_date(&self, source: &str) -> Option<NaiveDate> {
+        self.state.sources.get(source).map(|s| s.last_date)
+    }
+
+    pub fn advance(&mut self, source: &str, date: NaiveDate) -> Result<(), Error> {
+        self.state.sources.insert(
+            source.to_string(),
+            SourceState {
+                last_date: date,
+                updated_at: Utc::now(),
+            },
+        );
+
+        self.save()
+    }
+
+    /// Writes the state to a temporary file first so that a crash never leaves a truncated
+    /// state behind
+    fn save(&self) -> Result<(), Error> {
+        let content = serde_json::to_string_pretty(&self.state)
+            .map_err(|e| Error::Json(e, self.path.clone()))?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content).map_err(|e| Error::Write(e, tmp_path.clone()))?;
+        std::fs::rename(&tmp_path, &self.path).map_err(|e| Error::Write(e, self.path.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StateStore;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_state_store() {
+        let dir = std::env::temp_dir().join(format!("photon-state-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut store = StateStore::open(&dir).unwrap();
+        assert_eq!(store.last_date("eco2mix"), None);
+
+        store
+            .advance("eco2mix", NaiveDate::from_ymd_opt(2022, 10, 1).unwrap())
+            .unwrap();
+
+        let store = StateStore::open(&dir).unwrap();
+        assert_eq!(
+            store.last_date("eco2mix"),
+            Some(NaiveDate::from_ymd_opt(2022, 10, 1).unwrap())
+        );
+        assert_eq!(store.last_date("ecowatt"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
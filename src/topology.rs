@@ -1,37 +1,521 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+};
+
+use chrono::{DateTime, Utc};
+use tracing::{debug, error, field, info};
+
 use crate::{
+    config::Dates,
     point::Points,
+    pool,
+    report::{ComponentKind, Outcome, Report, Status},
+    schedule::Schedule,
     sink::Sink,
-    source::{DataSource, DataSourceResult},
+    source::{DataSource, DataSourceResult, GlobalConfig},
+    state::StateStore,
+    transform::Transform,
 };
 
+/// Names of the upstream components feeding a component, as glob patterns
+pub struct Inputs {
+    patterns: Vec<glob::Pattern>,
+
+    /// Components matched by a glob pattern but not fed to the component, see
+    /// `config::sort_transforms`
+    ignored: Vec<String>,
+}
+
+impl Inputs {
+    pub fn parse<S: AsRef<str>>(patterns: &[S]) -> Result<Self, glob::PatternError> {
+        let patterns = patterns
+            .iter()
+            .map(|p| glob::Pattern::new(p.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Inputs {
+            patterns,
+            ignored: Vec::new(),
+        })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.patterns.iter().any(|p| p.matches(name)) && !self.ignored.iter().any(|n| n == name)
+    }
+
+    /// Whether a pattern is the exact name of the component, rather than a glob matching it
+    pub fn names(&self, name: &str) -> bool {
+        let name = glob::Pattern::escape(name);
+        self.patterns.iter().any(|p| p.as_str() == name)
+    }
+
+    pub fn ignore(&mut self, name: &str) {
+        self.ignored.push(name.to_string());
+    }
+
+    /// Returns the first pattern that does not match any of the given names
+    pub fn unmatched<'a>(&self, names: impl Iterator<Item = &'a str> + Clone) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|p| !names.clone().any(|n| p.matches(n)))
+            .map(|p| p.as_str())
+    }
+}
+
 pub struct Component<T: ?Sized> {
     pub name: String,
 
+    pub inputs: Inputs,
+
     pub component: Box<T>,
 }
 
+pub struct DataSourceComponent {
+    pub name: String,
+
+    /// When set, the source is collected periodically and the process keeps running
+    /// until it receives SIGINT or SIGTERM
+    pub schedule: Option<Schedule>,
+
+    pub component: Box<dyn DataSource>,
+}
+
 pub struct Topology {
-    pub data_sources: Vec<Component<dyn DataSource>>,
+    pub dates: Dates,
+
+    /// Checkpoints of the data sources, when a state directory is configured
+    pub state: Option<StateStore>,
+
+    /// Where to write the JSON report at the end of every run
+    pub report_path: Option<PathBuf>,
+
+    /// Maximum number of data sources collected concurrently
+    pub workers: usize,
+
+    pub data_sources: Vec<DataSourceComponent>,
+
+    /// Transforms, ordered so that a transform always comes after its inputs
+    pub transforms: Vec<Component<dyn Transform>>,
 
     pub sinks: Vec<Component<dyn Sink>>,
 }
 
-fn collect(name: impl AsRef<str>, data_source: Box<dyn DataSource>) -> DataSourceResult<Points> {
-    let mut points = data_source.collect()?;
+fn collect(
+    name: impl AsRef<str>,
+    data_source: &dyn DataSource,
+    global: &GlobalConfig,
+) -> DataSourceResult<Points> {
+    let mut points = data_source.collect(global)?;
     points.tag_all("source", name);
     Ok(points)
 }
 
-pub fn run(topology: Topology) -> Result<(), Box<dyn std::error::Error>> {
-    let mut points = Points::new();
+/// Merges the points of the outputs routed to a component, `None` if there is none
+fn route<T: ?Sized>(component: &Component<T>, outputs: &[(&str, Points)]) -> Option<Points> {
+    let mut routed = outputs
+        .iter()
+        .filter(|(name, _)| component.inputs.matches(name))
+        .peekable();
+
+    routed.peek()?;
+
+    Some(Points::from_iter(
+        routed.flat_map(|(_, points)| points.iter().cloned()),
+    ))
+}
+
+impl Topology {
+    /// Transforms and sinks receiving, directly or not, the points of a data source
+    fn downstream(&self, data_source: &str) -> Vec<(ComponentKind, &str)> {
+        let mut reachable = vec![data_source];
+        let mut downstream = Vec::new();
+
+        for transform in &self.transforms {
+            if reachable.iter().any(|n| transform.inputs.matches(n)) {
+                reachable.push(&transform.name);
+                downstream.push((ComponentKind::Transform, transform.name.as_str()));
+            }
+        }
+
+        for sink in &self.sinks {
+            if reachable.iter().any(|n| sink.inputs.matches(n)) {
+                downstream.push((ComponentKind::Sink, sink.name.as_str()));
+            }
+        }
+
+        downstream
+    }
+
+    /// Collects the given data sources, using at most `workers` threads, and feeds the points
+    /// of the ones that succeeded through the transforms and sinks they are routed to.
+    /// Components fail independently from each other, each outcome being recorded in the
+    /// returned [`Report`]
+    fn run_once(
+        &self,
+        data_sources: &[&DataSourceComponent],
+        state: Option<&mut StateStore>,
+        workers: usize,
+    ) -> Report {
+        let mut report = Report::new();
+        let mut outputs = Vec::new();
+
+        // Components taking part in this run: the data sources being collected and,
+        // transitively, everything they are routed to
+        let mut active: Vec<&str> = data_sources.iter().map(|s| s.name.as_str()).collect();
+
+        let windows: Vec<_> = data_sources
+            .iter()
+            .map(|s| {
+                let last_date = state.as_ref().and_then(|state| state.last_date(&s.name));
+                self.dates.global(last_date)
+            })
+            .collect();
+
+        let sources: Vec<_> = data_sources.iter().zip(&windows).collect();
+        let collected = pool::map_ordered(&sources, workers, |(data_source, global)| {
+            let mut report = Report::new();
+            let points = report.record(
+                &data_source.name,
+                ComponentKind::Source,
+                Points::len,
+                || collect(&data_source.name, data_source.component.as_ref(), global),
+            );
+
+            (report, points)
+        });
+
+        for (data_source, (source_report, points)) in data_sources.iter().zip(collected) {
+            report.merge_with(source_report);
+
+            if let Some(points) = points {
+                outputs.push((data_source.name.as_str(), points));
+            }
+        }
+
+        for transform in &self.transforms {
+            if !active.iter().any(|n| transform.inputs.matches(n)) {
+                continue;
+            }
+
+            active.push(&transform.name);
+
+            let points = match route(transform, &outputs) {
+                Some(points) => points,
+                None => {
+                    report.skip(&transform.name, ComponentKind::Transform);
+                    continue;
+                }
+            };
+
+            let points = report.record(
+                &transform.name,
+                ComponentKind::Transform,
+                Points::len,
+                || transform.component.transform(points),
+            );
+
+            if let Some(points) = points {
+                outputs.push((transform.name.as_str(), points));
+            }
+        }
+
+        for sink in &self.sinks {
+            if !active.iter().any(|n| sink.inputs.matches(n)) {
+                continue;
+            }
+
+            let points = match route(sink, &outputs) {
+                Some(points) => points,
+                None => {
+                    report.skip(&sink.name, ComponentKind::Sink);
+                    continue;
+                }
+            };
+
+            debug!(sink = sink.name.as_str(), "sinking points");
+            report.record(
+                &sink.name,
+                ComponentKind::Sink,
+                |_| points.len(),
+                || sink.component.sink(&points),
+            );
+        }
+
+        if let Some(state) = state {
+            self.checkpoint(state, &report, data_sources, &windows);
+        }
+
+        report
+    }
+
+    /// Advances the checkpoint of the data sources whose points went through every transform
+    /// and sink they are routed to
+    fn checkpoint(
+        &self,
+        state: &mut StateStore,
+        report: &Report,
+        data_sources: &[&DataSourceComponent],
+        windows: &[GlobalConfig],
+    ) {
+        for (data_source, window) in data_sources.iter().zip(windows) {
+            let delivered = std::iter::once((ComponentKind::Source, data_source.name.as_str()))
+                .chain(self.downstream(&data_source.name))
+                .all(|(kind, name)| report.status(kind, name) == Some(Status::Success));
+
+            if !delivered {
+                continue;
+            }
+
+            if let Err(e) = state.advance(&data_source.name, window.to_date) {
+                error!(
+                    source = data_source.name.as_str(),
+                    "failed to save checkpoint: {e}"
+                );
+            }
+        }
+    }
+
+    fn publish(&self, report: &Report) {
+        report.log();
+
+        if let Some(path) = &self.report_path {
+            if let Err(e) = report.write(path) {
+                error!(
+                    path = field::display(path.display()),
+                    "failed to write run report: {e}"
+                );
+            }
+        }
+    }
+
+    fn run_scheduled(
+        &self,
+        data_sources: &[&DataSourceComponent],
+        mut state: Option<&mut StateStore>,
+        shutdown_rx: Receiver<()>,
+    ) -> Option<Outcome> {
+        let now = Utc::now();
+        let mut next_runs: Vec<_> = data_sources
+            .iter()
+            .map(|s| s.schedule.as_ref().and_then(|s| s.first(now)))
+            .collect();
+
+        info!(
+            sources = data_sources.len(),
+            "running scheduled data sources"
+        );
+
+        let mut outcome: Option<Outcome> = None;
+
+        loop {
+            let next = next_runs
+                .iter()
+                .enumerate()
+                .filter_map(|(i, at)| at.map(|at| (i, at)))
+                .min_by_key(|(_, at)| *at);
+
+            let (index, at) = match next {
+                Some(next) => next,
+                None => {
+                    info!("no more scheduled collection, exiting");
+                    break;
+                }
+            };
+
+            let data_source = data_sources[index];
+            let wait = (at - Utc::now()).to_std().unwrap_or_default();
+
+            info!(
+                source = data_source.name.as_str(),
+                at = %at,
+                "waiting for next collection"
+            );
+
+            // Signals are only observed between two collections so that in-flight writes
+            // always complete before shutting down
+            match shutdown_rx.recv_timeout(wait) {
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => {
+                    info!("received shutdown signal, exiting");
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            let report = self.run_once(&[data_source], state.as_deref_mut(), 1);
+            self.publish(&report);
+
+            let run = report.outcome();
+            outcome = Some(outcome.map_or(run, |o| o.combine(run)));
+
+            next_runs[index] = next_run(data_source, Utc::now());
+        }
+
+        outcome
+    }
+}
+
+fn next_run(data_source: &DataSourceComponent, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    data_source
+        .schedule
+        .as_ref()
+        .and_then(|s| s.next_after(after))
+}
+
+/// Runs the topology, collecting the sources without schedule once at startup then the sources
+/// with a schedule until a shutdown signal is received. Returns the combined outcome of all runs
+pub fn run(mut topology: Topology) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let mut state = topology.state.take();
+
+    let (scheduled, once): (Vec<_>, Vec<_>) = topology
+        .data_sources
+        .iter()
+        .partition(|s| s.schedule.is_some());
+
+    // The handler is installed before the first collection so that a signal received while it
+    // writes only stops the daemon once the collection completes
+    let shutdown_rx = if scheduled.is_empty() {
+        None
+    } else {
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        ctrlc::set_handler(move || {
+            let _ = shutdown_tx.send(());
+        })?;
+
+        Some(shutdown_rx)
+    };
+
+    let mut outcome = None;
+
+    if !once.is_empty() {
+        let report = topology.run_once(&once, state.as_mut(), topology.workers);
+        topology.publish(&report);
+        outcome = Some(report.outcome());
+    }
+
+    if let Some(shutdown_rx) = shutdown_rx {
+        if let Some(scheduled) = topology.run_scheduled(&scheduled, state.as_mut(), shutdown_rx) {
+            outcome = Some(outcome.map_or(scheduled, |o| o.combine(scheduled)));
+        }
+    }
+
+    Ok(outcome.unwrap_or(Outcome::Success))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Component, DataSourceComponent, Inputs, Topology};
+    use crate::{
+        config::{DateSpec, Dates, FromDate},
+        point::{Point, Points, Value},
+        report::{ComponentKind, Status},
+        sink::{Sink, SinkResult},
+        source::{DataSource, DataSourceResult, GlobalConfig},
+        state::StateStore,
+        value,
+    };
+    use chrono::NaiveDate;
+
+    struct Source;
 
-    for data_source in topology.data_sources {
-        points.merge_with(collect(&data_source.name, data_source.component)?);
+    impl DataSource for Source {
+        fn collect(&self, _global: &GlobalConfig) -> DataSourceResult<Points> {
+            Ok(Points::from(vec![Point::builder("test")
+                .field("value", value!(1))
+                .build()]))
+        }
     }
 
-    for sink in topology.sinks {
-        sink.component.sink(&points)?;
+    struct FailingSink(bool);
+
+    impl Sink for FailingSink {
+        fn sink(&self, _points: &Points) -> SinkResult<()> {
+            match self.0 {
+                true => Err("sink is down".into()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    fn sink(name: &str, inputs: &[&str], fail: bool) -> Component<dyn Sink> {
+        Component {
+            name: name.to_string(),
+            inputs: Inputs::parse(inputs).unwrap(),
+            component: Box::new(FailingSink(fail)),
+        }
     }
 
-    Ok(())
+    #[test]
+    fn test_checkpoint() {
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let dir = std::env::temp_dir().join(format!("photon-topology-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let topology = Topology {
+            dates: Dates {
+                from_date: FromDate::Last,
+                to_date: DateSpec::Date(date),
+                initial_date: None,
+            },
+            state: None,
+            report_path: None,
+            workers: 2,
+            data_sources: ["a", "b"]
+                .into_iter()
+                .map(|name| DataSourceComponent {
+                    name: name.to_string(),
+                    schedule: None,
+                    component: Box::new(Source),
+                })
+                .collect(),
+            transforms: Vec::new(),
+            sinks: vec![sink("all", &["*"], false), sink("down", &["b"], true)],
+        };
+
+        let mut state = StateStore::open(&dir).unwrap();
+        let data_sources: Vec<_> = topology.data_sources.iter().collect();
+        let report = topology.run_once(&data_sources, Some(&mut state), 2);
+
+        assert_eq!(
+            report.status(ComponentKind::Sink, "all"),
+            Some(Status::Success)
+        );
+        assert_eq!(
+            report.status(ComponentKind::Sink, "down"),
+            Some(Status::Failure)
+        );
+        assert_eq!(
+            report
+                .components
+                .iter()
+                .find(|c| c.name == "all")
+                .unwrap()
+                .points,
+            2
+        );
+
+        assert_eq!(state.last_date("a"), Some(date));
+        assert_eq!(state.last_date("b"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_inputs() {
+        let inputs = Inputs::parse(&["eco2mix-*", "ecowatt"]).unwrap();
+
+        assert!(inputs.matches("eco2mix-france"));
+        assert!(inputs.matches("ecowatt"));
+        assert!(!inputs.matches("ecowatt-sandbox"));
+
+        assert_eq!(
+            inputs.unmatched(["eco2mix-france", "ecowatt"].into_iter()),
+            None
+        );
+        assert_eq!(
+            inputs.unmatched(["eco2mix-france"].into_iter()),
+            Some("ecowatt")
+        );
+        assert!(Inputs::parse(&["eco2mix-["]).is_err());
+    }
 }
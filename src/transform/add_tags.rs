@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{point::Points, transform::Registration};
+
+use super::{Transform, TransformConfig, TransformResult};
+
+struct AddTags {
+    tags: HashMap<String, String>,
+}
+
+impl Transform for AddTags {
+    fn transform(&self, mut points: Points) -> TransformResult<Points> {
+        for (k, v) in &self.tags {
+            points.tag_all(k, v);
+        }
+
+        Ok(points)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    tags: HashMap<String, String>,
+}
+
+impl TransformConfig for Config {
+    fn build(self) -> TransformResult<Box<dyn Transform>> {
+        Ok(Box::new(AddTags { tags: self.tags }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("add-tags")
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    point::{Point, Points},
+    transform::Registration,
+};
+
+use super::{Transform, TransformConfig, TransformResult};
+
+struct DropFields {
+    fields: Vec<String>,
+}
+
+impl Transform for DropFields {
+    fn transform(&self, points: Points) -> TransformResult<Points> {
+        Ok(points
+            .into_iter()
+            .map(|mut point| {
+                for field in &self.fields {
+                    point.fields.remove(field);
+                }
+
+                point
+            })
+            // A point without any field can not be written by most sinks
+            .filter(|point: &Point| !point.fields.is_empty())
+            .collect())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    fields: Vec<String>,
+}
+
+impl TransformConfig for Config {
+    fn build(self) -> TransformResult<Box<dyn Transform>> {
+        Ok(Box::new(DropFields {
+            fields: self.fields,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("drop-fields")
+}
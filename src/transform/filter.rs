@@ -0,0 +1,238 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+use crate::{
+    point::{Point, Points, Value},
+    transform::Registration,
+};
+
+use super::{Transform, TransformConfig, TransformResult};
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("condition must have exactly one of `tag` or `field`")]
+    Target,
+}
+
+fn literal<'de, D>(deserializer: D) -> Result<Value, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Literal {
+        Integer(i64),
+        Float(f64),
+        Boolean(bool),
+        String(String),
+    }
+
+    Ok(match Literal::deserialize(deserializer)? {
+        Literal::Integer(i) => Value::Integer(i),
+        Literal::Float(f) => Value::Float(f),
+        Literal::Boolean(b) => Value::Boolean(b),
+        Literal::String(s) => Value::String(s),
+    })
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        Value::Boolean(_) => None,
+        Value::String(s) => s.parse().ok(),
+    }
+}
+
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Boolean(a), Value::Boolean(b)) => a == b,
+        _ => matches!((as_f64(a), as_f64(b)), (Some(a), Some(b)) if a == b),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Op {
+    Eq(#[serde(deserialize_with = "literal")] Value),
+
+    Ne(#[serde(deserialize_with = "literal")] Value),
+
+    Gt(f64),
+
+    Ge(f64),
+
+    Lt(f64),
+
+    Le(f64),
+
+    Exists(bool),
+}
+
+impl Op {
+    fn test(&self, value: Option<&Value>) -> bool {
+        let compare = |f: &dyn Fn(f64) -> bool| value.and_then(as_f64).is_some_and(f);
+
+        match self {
+            Op::Eq(expected) => value.is_some_and(|v| equals(v, expected)),
+            Op::Ne(expected) => value.is_none_or(|v| !equals(v, expected)),
+            Op::Gt(t) => compare(&|v| v > *t),
+            Op::Ge(t) => compare(&|v| v >= *t),
+            Op::Lt(t) => compare(&|v| v < *t),
+            Op::Le(t) => compare(&|v| v <= *t),
+            Op::Exists(exists) => value.is_some() == *exists,
+        }
+    }
+}
+
+enum Target {
+    Tag(String),
+
+    Field(String),
+}
+
+struct Condition {
+    target: Target,
+
+    op: Op,
+}
+
+impl Condition {
+    fn matches(&self, point: &Point) -> bool {
+        match &self.target {
+            Target::Tag(key) => {
+                let tag = point.tags.get(key).map(|v| Value::String(v.clone()));
+                self.op.test(tag.as_ref())
+            }
+            Target::Field(key) => self.op.test(point.fields.get(key)),
+        }
+    }
+}
+
+/// Keeps the points matching all the conditions
+struct Filter {
+    conditions: Vec<Condition>,
+}
+
+impl Transform for Filter {
+    fn transform(&self, points: Points) -> TransformResult<Points> {
+        Ok(points
+            .into_iter()
+            .filter(|p| self.conditions.iter().all(|c| c.matches(p)))
+            .collect())
+    }
+}
+
+/// A single predicate, e.g `{ field = "nuclear", gt = 30000 }` or `{ tag = "source", eq = "eco2mix" }`
+#[derive(Serialize, Deserialize, Debug)]
+struct ConditionConfig {
+    tag: Option<String>,
+
+    field: Option<String>,
+
+    #[serde(flatten)]
+    op: Op,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    conditions: Vec<ConditionConfig>,
+}
+
+impl TransformConfig for Config {
+    fn build(self) -> TransformResult<Box<dyn Transform>> {
+        let conditions = self
+            .conditions
+            .into_iter()
+            .map(|c| {
+                let target = match (c.tag, c.field) {
+                    (Some(tag), None) => Target::Tag(tag),
+                    (None, Some(field)) => Target::Field(field),
+                    _ => return Err(Error::Target),
+                };
+
+                Ok(Condition { target, op: c.op })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Box::new(Filter { conditions }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("filter")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::{
+        point::{Point, Points, Value},
+        transform::TransformConfig,
+        value,
+    };
+
+    fn filter(config: &str, points: Vec<Point>) -> Vec<Point> {
+        let config: Config = toml::from_str(config).unwrap();
+        let filter = config.build().unwrap();
+
+        filter
+            .transform(Points::from(points))
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    fn point(nuclear: i64, region: &str) -> Point {
+        let mut point = Point::builder("eco2mix")
+            .field("nuclear", value!(nuclear))
+            .build();
+        point.tags.insert("region".to_string(), region.to_string());
+        point
+    }
+
+    #[test]
+    fn test_filter() {
+        let points = || vec![point(100, "fr"), point(200, "be"), point(300, "fr")];
+
+        let kept = filter(
+            r#"conditions = [{ field = "nuclear", gt = 150 }, { tag = "region", eq = "fr" }]"#,
+            points(),
+        );
+        assert_eq!(kept.len(), 1);
+        assert!(matches!(kept[0].fields["nuclear"], Value::Integer(300)));
+
+        assert_eq!(
+            filter(
+                r#"conditions = [{ field = "nuclear", eq = 200.0 }]"#,
+                points()
+            )
+            .len(),
+            1
+        );
+        assert_eq!(
+            filter(r#"conditions = [{ tag = "region", ne = "fr" }]"#, points()).len(),
+            1
+        );
+        assert_eq!(
+            filter(
+                r#"conditions = [{ field = "coal", exists = false }]"#,
+                points()
+            )
+            .len(),
+            3
+        );
+        assert_eq!(
+            filter(r#"conditions = [{ field = "coal", lt = 1 }]"#, points()).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_invalid_target() {
+        let config: Config =
+            toml::from_str(r#"conditions = [{ tag = "a", field = "b", eq = 1 }]"#).unwrap();
+        assert!(config.build().is_err());
+    }
+}
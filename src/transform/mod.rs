@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::point::Points;
+
+mod add_tags;
+mod drop_fields;
+mod filter;
+mod rename;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("unknown transform {0}")]
+    Unknown(String),
+
+    #[error("invalid TOML: {0}")]
+    Toml(#[source] toml::de::Error),
+
+    #[error("invalid configuration for {1}: {0}")]
+    Config(#[source] Box<dyn std::error::Error>, String),
+}
+
+pub type TransformResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub trait Transform {
+    fn transform(&self, points: Points) -> TransformResult<Points>;
+}
+
+pub trait TransformConfig: Send + Sync {
+    fn build(self) -> TransformResult<Box<dyn Transform>>;
+}
+
+type Builder = fn(&str, toml::Value) -> Result<Box<dyn Transform>, Error>;
+
+pub struct Registration {
+    name: &'static str,
+
+    builder: Builder,
+}
+
+impl Registration {
+    pub const fn new<'a, TC>(name: &'static str) -> Self
+    where
+        TC: TransformConfig + serde::Deserialize<'a>,
+    {
+        let builder = |name: &str, value: toml::Value| {
+            let config: TC = value.try_into().map_err(Error::Toml)?;
+            config
+                .build()
+                .map_err(|e| Error::Config(e, name.to_string()))
+        };
+
+        Self { name, builder }
+    }
+
+    pub fn build(name: &str, value: toml::Value) -> Result<Box<dyn Transform>, Error> {
+        let registrations: HashMap<&'static str, &Registration> = inventory::iter::<Registration>()
+            .map(|r| (r.name, r))
+            .collect();
+
+        registrations
+            .get(name)
+            .ok_or(Error::Unknown(name.to_string()))
+            .and_then(|r| {
+                let builder = r.builder;
+
+                builder(name, value)
+            })
+    }
+}
+
+inventory::collect!(Registration);
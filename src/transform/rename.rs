@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{point::Points, transform::Registration};
+
+use super::{Transform, TransformConfig, TransformResult};
+
+/// Renames the keys of a map, all the keys being removed before any is inserted so that swaps
+/// and chains like `{ a="b", b="c" }` do not depend on the iteration order
+fn rename_keys<V>(map: &mut HashMap<String, V>, renames: &HashMap<String, String>) {
+    let renamed: Vec<_> = renames
+        .iter()
+        .filter_map(|(from, to)| map.remove(from).map(|value| (to.clone(), value)))
+        .collect();
+
+    map.extend(renamed);
+}
+
+struct Rename {
+    measurements: HashMap<String, String>,
+
+    fields: HashMap<String, String>,
+
+    tags: HashMap<String, String>,
+}
+
+impl Transform for Rename {
+    fn transform(&self, points: Points) -> TransformResult<Points> {
+        Ok(points
+            .into_iter()
+            .map(|mut point| {
+                if let Some(name) = self.measurements.get(&point.name) {
+                    point.name = name.clone();
+                }
+
+                rename_keys(&mut point.fields, &self.fields);
+                rename_keys(&mut point.tags, &self.tags);
+
+                point
+            })
+            .collect())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    #[serde(default)]
+    measurements: HashMap<String, String>,
+
+    #[serde(default)]
+    fields: HashMap<String, String>,
+
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+impl TransformConfig for Config {
+    fn build(self) -> TransformResult<Box<dyn Transform>> {
+        Ok(Box::new(Rename {
+            measurements: self.measurements,
+
+            fields: self.fields,
+
+            tags: self.tags,
+        }))
+    }
+}
+
+inventory::submit! {
+    Registration::new::<Config>("rename")
+}
+
+#[cfg(test)]
+mod test {
+    use super::rename_keys;
+    use std::collections::HashMap;
+
+    fn map(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_rename_keys() {
+        let mut tags = map(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        rename_keys(&mut tags, &map(&[("a", "b"), ("b", "a")]));
+        assert_eq!(tags, map(&[("a", "2"), ("b", "1"), ("c", "3")]));
+
+        let mut tags = map(&[("a", "1"), ("b", "2")]);
+        rename_keys(&mut tags, &map(&[("a", "b"), ("b", "c")]));
+        assert_eq!(tags, map(&[("b", "1"), ("c", "2")]));
+    }
+}